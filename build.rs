@@ -0,0 +1,29 @@
+//! Lists the migrations in `migrations/`, so the gameserver can tell which ones a db is missing
+//! without running them.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+
+    let mut migrations = fs::read_dir("migrations")
+        .expect("couldn't read migrations")
+        .map(|entry| entry.expect("couldn't read migrations").file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .filter(|name| !name.starts_with('.'))
+        .collect::<Vec<_>>();
+    migrations.sort();
+
+    // diesel records a migration by its name up to the first `_`, without dashes
+    let entries = migrations
+        .iter()
+        .map(|name| {
+            let version = name.split('_').next().unwrap().replace('-', "");
+            format!("    ({:?}, {:?}),\n", version, name)
+        })
+        .collect::<String>();
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("migrations.rs");
+    fs::write(out, format!("&[\n{}]\n", entries)).expect("couldn't write the migration list");
+}
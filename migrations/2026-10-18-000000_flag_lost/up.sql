@@ -0,0 +1 @@
+ALTER TABLE "flags" ADD COLUMN "lost" BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,8 @@
+ALTER TABLE "flags" ADD COLUMN "defended" BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE "flags" SET "defended" = "status" IN ('ok', 'error') AND NOT "lost";
+ALTER TABLE "flags" DROP COLUMN "status";
+
+ALTER TABLE "check_ups" ADD COLUMN "up" BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE "check_ups" SET "up" = "status" IN ('ok', 'error');
+ALTER TABLE "check_ups" ALTER COLUMN "up" DROP DEFAULT;
+ALTER TABLE "check_ups" DROP COLUMN "status";
@@ -0,0 +1,9 @@
+ALTER TABLE "check_ups" ADD COLUMN "status" VARCHAR(8);
+UPDATE "check_ups" SET "status" = CASE WHEN "up" THEN 'ok' ELSE 'down' END;
+ALTER TABLE "check_ups" ALTER COLUMN "status" SET NOT NULL;
+ALTER TABLE "check_ups" DROP COLUMN "up";
+
+-- NULL until the flag has been checked with get_flag
+ALTER TABLE "flags" ADD COLUMN "status" VARCHAR(8);
+UPDATE "flags" SET "status" = CASE WHEN "defended" THEN 'ok' ELSE 'down' END;
+ALTER TABLE "flags" DROP COLUMN "defended";
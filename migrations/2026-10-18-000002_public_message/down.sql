@@ -0,0 +1,2 @@
+ALTER TABLE "check_ups" DROP COLUMN "message";
+ALTER TABLE "flags" DROP COLUMN "message";
@@ -0,0 +1,3 @@
+-- the last `PUBLIC:` line a checker printed to stderr, shown to the team
+ALTER TABLE "check_ups" ADD COLUMN "message" TEXT;
+ALTER TABLE "flags" ADD COLUMN "message" TEXT;
@@ -0,0 +1,17 @@
+-- every checker run, including the ones that were retried; the verdict that counts is still
+-- the one in "check_ups" or "flags"
+CREATE TABLE IF NOT EXISTS "attempts" (
+    "id" SERIAL PRIMARY KEY,
+    "action" VARCHAR(8) NOT NULL,
+    "tick" INTEGER NOT NULL,
+    "team_id" INTEGER NOT NULL,
+    "service_name" VARCHAR(16) NOT NULL,
+    "attempt" INTEGER NOT NULL,
+    "status" VARCHAR(8) NOT NULL,
+    "message" TEXT,
+    "duration_ms" INTEGER NOT NULL,
+    "timestamp" TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    FOREIGN KEY ("team_id") REFERENCES "teams"("id"),
+    FOREIGN KEY ("service_name") REFERENCES "services"("name")
+);
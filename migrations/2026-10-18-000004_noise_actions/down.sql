@@ -0,0 +1,2 @@
+DELETE FROM "attempts" WHERE LENGTH("action") > 8;
+ALTER TABLE "attempts" ALTER COLUMN "action" TYPE VARCHAR(8);
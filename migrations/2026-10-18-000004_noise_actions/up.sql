@@ -0,0 +1,2 @@
+-- room for the noise actions' names, like "put_noise"
+ALTER TABLE "attempts" ALTER COLUMN "action" TYPE VARCHAR(16);
@@ -0,0 +1,2 @@
+-- FALSE if set_flag failed, in which case "status" is set_flag's verdict rather than get_flag's
+ALTER TABLE "flags" ADD COLUMN "planted" BOOLEAN NOT NULL DEFAULT TRUE;
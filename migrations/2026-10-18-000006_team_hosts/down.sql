@@ -0,0 +1,8 @@
+-- anything that isn't an IPv4 address becomes 0.0.0.0
+ALTER TABLE "teams" ALTER COLUMN "ip" TYPE INTEGER USING CASE
+	WHEN "ip" ~ '^\d{1,3}\.\d{1,3}\.\d{1,3}\.\d{1,3}$' THEN (
+		(SPLIT_PART("ip", '.', 1)::BIGINT << 24) | (SPLIT_PART("ip", '.', 2)::BIGINT << 16) |
+		(SPLIT_PART("ip", '.', 3)::BIGINT << 8) | SPLIT_PART("ip", '.', 4)::BIGINT
+	)::BIT(32)::INTEGER
+	ELSE 0
+END;
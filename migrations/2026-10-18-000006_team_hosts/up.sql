@@ -0,0 +1,4 @@
+-- teams can be reached at IPv6 addresses and hostnames too, so keep them as they're written
+ALTER TABLE "teams" ALTER COLUMN "ip" TYPE TEXT USING
+	(("ip"::BIGINT >> 24) & 255) || '.' || (("ip"::BIGINT >> 16) & 255) || '.' ||
+	(("ip"::BIGINT >> 8) & 255) || '.' || ("ip"::BIGINT & 255);
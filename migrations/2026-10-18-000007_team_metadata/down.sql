@@ -0,0 +1,4 @@
+ALTER TABLE "teams" DROP COLUMN "logo";
+ALTER TABLE "teams" DROP COLUMN "country";
+ALTER TABLE "teams" DROP COLUMN "affiliation";
+ALTER TABLE "teams" DROP COLUMN "name";
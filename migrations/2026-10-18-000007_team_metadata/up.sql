@@ -0,0 +1,5 @@
+ALTER TABLE "teams" ADD COLUMN "name" TEXT;
+ALTER TABLE "teams" ADD COLUMN "affiliation" TEXT;
+-- an ISO 3166-1 alpha-2 code, like "DE"
+ALTER TABLE "teams" ADD COLUMN "country" VARCHAR(2);
+ALTER TABLE "teams" ADD COLUMN "logo" TEXT;
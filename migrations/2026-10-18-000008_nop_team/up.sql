@@ -0,0 +1,2 @@
+-- the reference team running the services as they were shipped, which isn't ranked
+ALTER TABLE "teams" ADD COLUMN "nop" BOOLEAN NOT NULL DEFAULT FALSE;
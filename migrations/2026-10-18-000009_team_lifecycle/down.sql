@@ -0,0 +1,3 @@
+DROP TABLE "audit_log";
+ALTER TABLE "teams" DROP COLUMN "added_by_admin";
+ALTER TABLE "teams" DROP COLUMN "state";
@@ -0,0 +1,16 @@
+-- "active", "suspended" (not checked until it's resumed) or "disqualified"
+ALTER TABLE "teams" ADD COLUMN "state" VARCHAR(16) NOT NULL DEFAULT 'active';
+-- teams added to a running game rather than in the config
+ALTER TABLE "teams" ADD COLUMN "added_by_admin" BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- every change an admin made to the teams
+CREATE TABLE IF NOT EXISTS "audit_log" (
+    "id" SERIAL PRIMARY KEY,
+    "timestamp" TIMESTAMP NOT NULL DEFAULT NOW(),
+    "actor" TEXT NOT NULL,
+    "action" VARCHAR(16) NOT NULL,
+    "team_id" INTEGER NOT NULL,
+    "detail" TEXT,
+
+    FOREIGN KEY ("team_id") REFERENCES "teams"("id")
+);
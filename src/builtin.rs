@@ -0,0 +1,209 @@
+use std::io;
+use std::net::SocketAddr;
+
+use futures::{future, Future, Stream};
+use hyper::header::HOST;
+use hyper::{Body, Client, Request, StatusCode, Uri};
+use regex::bytes::Regex;
+use tokio::net::TcpStream;
+
+use crate::checker::{CheckContext, CheckFuture};
+use crate::service::ServiceError;
+use crate::status::CheckerStatus;
+use crate::util::Locked;
+
+/// How much of a banner or response body is read before giving up on finding a match.
+const MAX_READ: usize = 64 * 1024;
+
+/// A check_up declared in a service's `meta.toml` instead of an executable.
+///
+/// # Examples
+///
+/// ```
+/// # use gameserver::builtin::{GenericCheck, GenericChecker};
+/// let check: GenericCheck = toml::from_str(r#"
+///     type = "http"
+///     path = "/login"
+///     body = "Sign in"
+/// "#).unwrap();
+/// assert_eq!(check, GenericCheck::Http {
+///     path: "/login".to_owned(),
+///     status: 200,
+///     body: Some("Sign in".to_owned()),
+/// });
+/// let relative: GenericCheck = toml::from_str(r#"
+///     type = "http"
+///     path = "login"
+/// "#).unwrap();
+/// assert!(GenericChecker::new(relative).is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GenericCheck {
+    /// The port accepts TCP connections.
+    TcpConnect,
+    /// The port accepts TCP connections, and what it sends first matches the `banner` regex.
+    TcpBanner { banner: String },
+    /// `GET path` returns `status`, and the body matches the `body` regex if one is given. The
+    /// path has to start with `/`, and the request is sent with the team's host in its `Host`
+    /// header.
+    Http {
+        #[serde(default = "GenericCheck::default_path")]
+        path: String,
+        #[serde(default = "GenericCheck::default_status")]
+        status: u16,
+        #[serde(default)]
+        body: Option<String>,
+    },
+}
+
+impl GenericCheck {
+    fn default_path() -> String {
+        "/".to_owned()
+    }
+
+    fn default_status() -> u16 {
+        200
+    }
+}
+
+/// A [`GenericCheck`] that's ready to run, with its regex compiled.
+#[derive(Clone)]
+pub struct GenericChecker {
+    check: GenericCheck,
+    pattern: Option<Regex>,
+    status: StatusCode,
+}
+
+impl GenericChecker {
+    pub fn new(check: GenericCheck) -> Result<Self, ServiceError> {
+        let pattern = match &check {
+            GenericCheck::TcpConnect => None,
+            GenericCheck::TcpBanner { banner: pattern }
+            | GenericCheck::Http {
+                body: Some(pattern),
+                ..
+            } => Some(Regex::new(pattern).map_err(ServiceError::Regex)?),
+            GenericCheck::Http { body: None, .. } => None,
+        };
+        if let GenericCheck::Http { path, .. } = &check {
+            if !path.starts_with('/') {
+                return Err(ServiceError::RelativePath(path.clone()));
+            }
+        }
+        let status = match &check {
+            GenericCheck::Http { status, .. } => {
+                StatusCode::from_u16(*status).map_err(ServiceError::Status)?
+            }
+            _ => StatusCode::OK,
+        };
+        Ok(GenericChecker {
+            check,
+            pattern,
+            status,
+        })
+    }
+
+    pub fn check_up(&self, ctx: CheckContext) -> CheckFuture<Option<String>> {
+        let ip = match ctx.addr {
+            Some(ip) => ip,
+            None => {
+                let err = io::Error::other("not looked up");
+                return Box::new(future::err(ServiceError::Resolve(ctx.target, err)));
+            }
+        };
+        let addr = SocketAddr::new(ip, ctx.port);
+        let pattern = self.pattern.clone();
+        match &self.check {
+            GenericCheck::TcpConnect => {
+                Box::new(TcpStream::connect(&addr).map(|_| None).map_err(unreachable))
+            }
+            GenericCheck::TcpBanner { .. } => {
+                let pattern = pattern.expect("banner checks always have a pattern");
+                let banner = TcpStream::connect(&addr)
+                    .map_err(unreachable)
+                    .and_then(move |stream| read_until_match(stream, pattern, Vec::new()));
+                Box::new(banner.and_then(|found| {
+                    if found {
+                        Ok(None)
+                    } else {
+                        Err(mumble("unexpected banner"))
+                    }
+                }))
+            }
+            GenericCheck::Http { path, .. } => {
+                let uri = match format!("http://{}{}", addr, path).parse::<Uri>() {
+                    Ok(uri) => uri,
+                    Err(err) => return Box::new(future::err(ServiceError::Uri(err))),
+                };
+                // the uri has the address the host resolved to, so name the host itself here
+                let host = match ctx.target.ip() {
+                    Some(ip) => SocketAddr::new(ip, ctx.port).to_string(),
+                    None => format!("{}:{}", ctx.target, ctx.port),
+                };
+                let request = Request::get(uri)
+                    .header(HOST, host)
+                    .body(Body::empty())
+                    .expect("hosts and addresses are valid header values");
+                let expected = self.status;
+                let response = Client::new().request(request).map_err(unreachable);
+                Box::new(Locked::new(response.and_then(move |response| {
+                    let status = response.status();
+                    if status != expected {
+                        let message = format!("expected status {}, got {}", expected, status);
+                        return future::Either::A(future::err(mumble(message)));
+                    }
+                    let body = response
+                        .into_body()
+                        .map_err(unreachable)
+                        .fold(Vec::new(), |mut body: Vec<u8>, chunk| {
+                            if body.len() < MAX_READ {
+                                body.extend_from_slice(&chunk);
+                            }
+                            Ok::<_, ServiceError>(body)
+                        })
+                        .and_then(move |body| match pattern {
+                            Some(pattern) if !pattern.is_match(&body) => {
+                                Err(mumble("unexpected response"))
+                            }
+                            _ => Ok(None),
+                        });
+                    future::Either::B(body)
+                })))
+            }
+        }
+    }
+}
+
+/// Reads from `stream` until what has been read matches `pattern`, returning whether it did
+/// before the stream ended or [`MAX_READ`] bytes were read.
+fn read_until_match(
+    stream: TcpStream,
+    pattern: Regex,
+    buf: Vec<u8>,
+) -> Box<dyn Future<Item = bool, Error = ServiceError> + Send + Sync> {
+    let chunk = vec![0; 4096];
+    Box::new(
+        tokio::io::read(stream, chunk)
+            .map_err(unreachable)
+            .and_then(move |(stream, chunk, n)| {
+                let mut buf = buf;
+                buf.extend_from_slice(&chunk[..n]);
+                if pattern.is_match(&buf) {
+                    future::Either::A(future::ok(true))
+                } else if n == 0 || buf.len() >= MAX_READ {
+                    future::Either::A(future::ok(false))
+                } else {
+                    future::Either::B(read_until_match(stream, pattern, buf))
+                }
+            }),
+    )
+}
+
+fn unreachable(err: impl ToString) -> ServiceError {
+    ServiceError::Verdict(CheckerStatus::Down, Some(err.to_string()))
+}
+
+fn mumble(message: impl Into<String>) -> ServiceError {
+    ServiceError::Verdict(CheckerStatus::Mumble, Some(message.into()))
+}
@@ -6,15 +6,14 @@ use std::time::{Duration, Instant};
 use chrono::{DateTime, Utc};
 use tokio::{prelude::*, timer::Delay};
 
+use crate::schedule::Schedule;
+use crate::shutdown::Shutdown;
 use crate::GameServer;
 
-pub fn ticker(gs: Arc<Mutex<GameServer>>) -> impl Future<Item = (), Error = ()> {
-    let (interval, log_directory) = {
-        let gs = gs.lock().unwrap();
-        let config = gs.get_config();
-        (config.check_period, config.log_directory.clone())
-    };
-
+pub fn ticker(
+    gs: Arc<Mutex<GameServer>>,
+    shutdown: Shutdown,
+) -> impl Future<Item = (), Error = ()> {
     // get the latest tick number
     let check_number = {
         let gs = gs.lock().unwrap();
@@ -29,21 +28,29 @@ pub fn ticker(gs: Arc<Mutex<GameServer>>) -> impl Future<Item = (), Error = ()>
         interval: u64,
         gs: Arc<Mutex<GameServer>>,
         log_directory: impl AsRef<Path>,
+        shutdown: Shutdown,
     ) -> impl Future<Item = (), Error = ()> + Send + Sync {
         // get teams
-        let teams = {
+        let (mut teams, spread, services) = {
             let gs = gs.clone();
             let gs = gs.lock().unwrap();
-            gs.get_teams()
+            (gs.get_teams(), gs.get_config().spread, gs.service_count())
         };
 
+        // start each team's jobs at a different time every round, and queue the teams for checker
+        // slots in a different order, so the same teams don't always get the first slots
+        let schedule = Schedule::new(Duration::from_secs(interval), spread, teams.len(), services);
+        info!("check {}: schedule seed {}", check_number, schedule.seed());
+        schedule.shuffle(&mut teams);
+
         let mut fut: Box<Future<Item = (), Error = ()> + Send + Sync> = Box::new(future::ok(()));
         for team in teams {
             // log dir
             let log_dir = log_directory
                 .as_ref()
                 .join("check_up")
-                .join(format!("team_{}", team.id));
+                .join(format!("team_{:02}", team.id))
+                .join(format!("check_{:03}", check_number));
             if !log_dir.exists() {
                 fs::create_dir_all(&log_dir);
             }
@@ -52,12 +59,29 @@ pub fn ticker(gs: Arc<Mutex<GameServer>>) -> impl Future<Item = (), Error = ()>
             let gs = gs.lock().unwrap();
             let db = gs.get_db();
             fut = Box::new(
-                fut.join(gs.check_up(db, check_number, now, team.id, team.ip, log_dir))
-                    .map(|_| ()),
+                fut.join(gs.check_up(
+                    db,
+                    schedule,
+                    check_number,
+                    now,
+                    team,
+                    log_dir,
+                    shutdown.clone(),
+                ))
+                .map(|_| ()),
             );
         }
 
+        let interrupted = shutdown.clone();
         fut = Box::new(fut.and_then(move |_| {
+            // jobs that were skipped for the shutdown are run when the round is resumed
+            if interrupted.is_requested() {
+                info!(
+                    "check {} was cut short, it's resumed on restart",
+                    check_number
+                );
+                return Ok(());
+            }
             let gs = gs.clone();
             let gs = gs.lock().unwrap();
             let db = gs.get_db();
@@ -69,15 +93,31 @@ pub fn ticker(gs: Arc<Mutex<GameServer>>) -> impl Future<Item = (), Error = ()>
         // delay
         Box::new(
             fut.join(
-                Delay::new(Instant::now() + Duration::from_secs(interval)).map_err(|err| {
-                    error!("Timer error: {}", err);
-                }),
+                Delay::new(Instant::now() + Duration::from_secs(interval))
+                    .map_err(|err| {
+                        error!("Timer error: {}", err);
+                    })
+                    // don't sit out the rest of the round if we're shutting down
+                    .select(shutdown.wait())
+                    .map(|_| ())
+                    .map_err(|_| ()),
             )
             .map(|_| ()),
         )
     }
 
     stream::unfold(check_number, move |check_number| {
+        if shutdown.is_requested() {
+            info!("shutdown requested, not starting check {}", check_number);
+            return None;
+        }
+
+        // reloads are applied by the flag ticker, so this picks up whatever it last loaded
+        let (interval, log_directory) = {
+            let gs = gs.lock().unwrap();
+            let config = gs.get_config();
+            (config.check_period, config.log_directory.clone())
+        };
         let now = Utc::now();
         let interval = interval.into();
         Some(
@@ -86,7 +126,8 @@ pub fn ticker(gs: Arc<Mutex<GameServer>>) -> impl Future<Item = (), Error = ()>
                 now,
                 interval,
                 gs.clone(),
-                log_directory.clone(),
+                log_directory,
+                shutdown.clone(),
             )
             .map(move |_| ((), check_number + 1)),
         )
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{future, Future};
+
+use crate::host::Host;
+use crate::service::ServiceError;
+
+/// Prefix of the `checker` setting in `meta.toml` that refers to a checker in the [`Registry`].
+pub const BUILTIN_PREFIX: &str = "builtin:";
+
+pub type CheckFuture<T> = Box<dyn Future<Item = T, Error = ServiceError> + Send + Sync>;
+
+/// The things a checker is asked to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    CheckUp,
+    SetFlag,
+    GetFlag,
+    /// One of the optional actions that only make traffic.
+    Noise(Noise),
+}
+
+impl Action {
+    /// The short name checkers are told the action by: `check`, `put`, `get`, or the noise
+    /// action's name.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Action::CheckUp => "check",
+            Action::SetFlag => "put",
+            Action::GetFlag => "get",
+            Action::Noise(noise) => noise.as_str(),
+        }
+    }
+
+    /// The action's name in logs: `check_up`, `set_flag`, `get_flag`, or the noise action's name.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::CheckUp => "check_up",
+            Action::SetFlag => "set_flag",
+            Action::GetFlag => "get_flag",
+            Action::Noise(noise) => noise.as_str(),
+        }
+    }
+}
+
+/// Actions that use a service the way players do without touching flags, so the gameserver's
+/// traffic can't be told apart by only ever planting and retrieving flags. A service lists the
+/// ones it has in `noise`, and they run at random points of every check round.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Noise {
+    /// Stores data that isn't a flag.
+    PutNoise,
+    /// Reads data back, such as what an earlier `put_noise` stored.
+    GetNoise,
+    /// Anything else a player might do, like malformed or unusual requests.
+    Havoc,
+}
+
+impl Noise {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Noise::PutNoise => "put_noise",
+            Noise::GetNoise => "get_noise",
+            Noise::Havoc => "havoc",
+        }
+    }
+}
+
+/// What a checker needs to know about the run it's doing.
+#[derive(Clone, Debug)]
+pub struct CheckContext {
+    pub action: Action,
+    pub service: String,
+    pub team_id: i32,
+    /// The tick the flag belongs to, or the check number for check_ups.
+    pub tick: i32,
+    /// Where the team runs the service, as it's written in the config.
+    pub target: Host,
+    /// What `target` resolved to for this run. The gameserver fills it in before calling a
+    /// checker, so it's only missing in contexts made by hand for hostnames.
+    pub addr: Option<IpAddr>,
+    pub port: u16,
+    /// How long the run may take. The gameserver gives up on the run once it's over, so checkers
+    /// don't have to enforce it themselves.
+    pub timeout: Duration,
+    /// A directory the checker may write its own logs to.
+    pub log_dir: PathBuf,
+}
+
+/// A checker that runs inside the gameserver rather than as a separate executable.
+///
+/// Like the executables, a checker reports anything other than `Ok` by failing with
+/// [`ServiceError::Verdict`], and can hand back a public message to show the team.
+///
+/// # Examples
+///
+/// ```
+/// # use futures::future;
+/// # use gameserver::checker::{CheckContext, CheckFuture, Checker, Registry};
+/// # use gameserver::service::ServiceError;
+/// # use gameserver::status::CheckerStatus;
+/// struct AlwaysDown;
+///
+/// impl Checker for AlwaysDown {
+///     fn check_up(&self, _: CheckContext) -> CheckFuture<Option<String>> {
+///         let message = Some("nothing to see here".to_owned());
+///         Box::new(future::err(ServiceError::Verdict(CheckerStatus::Down, message)))
+///     }
+///
+///     fn set_flag(&self, _: CheckContext, _: String) -> CheckFuture<(Option<String>, Option<String>)> {
+///         Box::new(future::err(ServiceError::Verdict(CheckerStatus::Down, None)))
+///     }
+///
+///     fn get_flag(&self, _: CheckContext, _: Option<String>) -> CheckFuture<(String, Option<String>)> {
+///         Box::new(future::err(ServiceError::Verdict(CheckerStatus::Down, None)))
+///     }
+/// }
+///
+/// let mut checkers = Registry::new();
+/// checkers.register("always_down", AlwaysDown);
+/// assert!(checkers.resolve("builtin:always_down").is_ok());
+/// assert!(checkers.resolve("builtin:missing").is_err());
+/// ```
+pub trait Checker: Send + Sync {
+    /// Checks that the service works, returning its public message.
+    fn check_up(&self, ctx: CheckContext) -> CheckFuture<Option<String>>;
+
+    /// Plants `flag`, returning the flag id to pass to `get_flag` later and a public message.
+    fn set_flag(
+        &self,
+        ctx: CheckContext,
+        flag: String,
+    ) -> CheckFuture<(Option<String>, Option<String>)>;
+
+    /// Retrieves the flag planted under `flag_id`, returning it and a public message.
+    fn get_flag(
+        &self,
+        ctx: CheckContext,
+        flag_id: Option<String>,
+    ) -> CheckFuture<(String, Option<String>)>;
+
+    /// Runs the noise action in `ctx.action`, returning a public message. Only the actions in
+    /// the service's `noise` list are run, and checkers without any don't need to implement it.
+    fn noise(&self, _ctx: CheckContext) -> CheckFuture<Option<String>> {
+        Box::new(future::err(ServiceError::NoNoise))
+    }
+}
+
+/// The checkers services can refer to by name, with `checker = "builtin:<name>"`.
+#[derive(Clone, Default)]
+pub struct Registry {
+    checkers: HashMap<String, Arc<dyn Checker>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    pub fn register(&mut self, name: impl Into<String>, checker: impl Checker + 'static) {
+        self.checkers.insert(name.into(), Arc::new(checker));
+    }
+
+    pub fn get(&self, name: impl AsRef<str>) -> Option<Arc<dyn Checker>> {
+        self.checkers.get(name.as_ref()).cloned()
+    }
+
+    /// Looks up the checker a service's `checker` setting refers to.
+    pub fn resolve(&self, reference: impl AsRef<str>) -> Result<Arc<dyn Checker>, ServiceError> {
+        let reference = reference.as_ref();
+        if !reference.starts_with(BUILTIN_PREFIX) {
+            return Err(ServiceError::UnknownChecker(reference.to_owned()));
+        }
+        self.get(&reference[BUILTIN_PREFIX.len()..])
+            .ok_or_else(|| ServiceError::UnknownChecker(reference.to_owned()))
+    }
+}
@@ -1,10 +1,107 @@
-use std::net::{Ipv4Addr, SocketAddr};
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::net::SocketAddr;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use serde::de::{self, Deserialize, Deserializer};
+
+use crate::host::Host;
+use crate::util::ResourceLimits;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct TeamConfig {
     pub id: i32,
-    pub ip: Ipv4Addr,
+    /// The team's vulnbox, as an IPv4 or IPv6 address or a hostname.
+    pub ip: Host,
+    /// What the scoreboard calls the team, rather than `Team <id>`.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The university, company or country the team plays for.
+    #[serde(default)]
+    pub affiliation: Option<String>,
+    /// The team's ISO 3166-1 alpha-2 country code, like `DE`.
+    #[serde(default, deserialize_with = "deserialize_country")]
+    pub country: Option<String>,
+    /// The URL of the team's logo, which may be relative to the scoreboard.
+    #[serde(default)]
+    pub logo: Option<String>,
+    /// Marks a reference team that runs the services as they were shipped. It's checked and
+    /// can be attacked like any other team, but isn't ranked.
+    #[serde(default)]
+    pub nop: bool,
+    /// Where the team runs particular services, by service name, if not on `ip` at the
+    /// service's port.
+    #[serde(default)]
+    pub services: HashMap<String, ServiceAddress>,
+}
+
+/// Checks that `country` is a two-letter code like `DE`, which is all the db has room for.
+///
+/// # Examples
+///
+/// ```
+/// # use gameserver::parse_country;
+/// assert_eq!(parse_country("DE"), Ok("DE".to_owned()));
+/// assert!(parse_country("Germany").is_err());
+/// assert!(parse_country("de").is_err());
+/// ```
+pub fn parse_country(country: &str) -> Result<String, String> {
+    if country.len() == 2 && country.chars().all(|c| c.is_ascii_uppercase()) {
+        Ok(country.to_owned())
+    } else {
+        Err(format!(
+            "{:?} isn't a two-letter country code like \"DE\"",
+            country
+        ))
+    }
+}
+
+fn deserialize_country<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(country) => parse_country(&country).map(Some).map_err(de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+/// Overrides for where a team runs one service.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ServiceAddress {
+    #[serde(default)]
+    pub ip: Option<Host>,
+    #[serde(default)]
+    pub port: Option<u16>,
+}
+
+impl TeamConfig {
+    /// Where the team runs `service`, which listens on `port` unless the team says otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use gameserver::TeamConfig;
+    /// let team: TeamConfig = toml::from_str(r#"
+    ///     id = 1
+    ///     ip = "10.0.0.1"
+    ///     services = { notes = { ip = "notes.team1.ctf" }, bank = { port = 8443 } }
+    /// "#).unwrap();
+    /// let address = |service, port| {
+    ///     let (host, port) = team.address(service, port);
+    ///     (host.to_string(), port)
+    /// };
+    /// assert_eq!(address("notes", 9999), ("notes.team1.ctf".to_owned(), 9999));
+    /// assert_eq!(address("bank", 443), ("10.0.0.1".to_owned(), 8443));
+    /// assert_eq!(address("chat", 6667), ("10.0.0.1".to_owned(), 6667));
+    /// ```
+    pub fn address(&self, service: &str, port: u16) -> (Host, u16) {
+        let address = self.services.get(service).cloned().unwrap_or_default();
+        let ip = address.ip.unwrap_or_else(|| self.ip.clone());
+        (ip, address.port.unwrap_or(port))
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -12,7 +109,24 @@ pub struct Config {
     pub flag_period: u32,
     pub check_period: u32,
     pub delay: u32,
+    /// If set, jobs start at random points in this fraction of the round, between 0 and 1,
+    /// rather than in the first `delay` seconds.
+    #[serde(default)]
+    pub spread: Option<f64>,
     pub timeout: u32,
+    /// Maximum number of checkers that may run at once across all services, or unlimited if
+    /// unset.
+    #[serde(default)]
+    pub max_checkers: Option<NonZeroUsize>,
+    /// Seconds to wait for running checkers after a shutdown is requested before killing them.
+    #[serde(default = "Config::default_shutdown_timeout")]
+    pub shutdown_timeout: u32,
+    /// How many ticks back to look for flags to check with get_flag.
+    #[serde(default = "Config::default_flag_retention")]
+    pub flag_retention: NonZeroU32,
+    /// If set, only this many of those flags are checked each tick, chosen at random.
+    #[serde(default)]
+    pub flag_sample: Option<NonZeroUsize>,
     pub teams: Vec<TeamConfig>,
 
     pub db: String,
@@ -20,6 +134,61 @@ pub struct Config {
     pub ignores: Vec<String>,
 
     pub log_directory: PathBuf,
+    /// Maximum number of bytes a checker may write to each of stdout and stderr, or unlimited if
+    /// unset. Checkers that write more are killed.
+    #[serde(default)]
+    pub log_max_bytes: Option<usize>,
+    /// Resource limits for every checker, unless its service sets its own.
+    #[serde(default)]
+    pub limits: ResourceLimits,
     pub bind_addr: SocketAddr,
     pub secret_key: String,
+    /// What the admin endpoints expect in the `X-Admin-Key` header, or unset to turn them off.
+    /// It's kept apart from `secret_key`, which checker seeds are derived from.
+    #[serde(default)]
+    pub admin_key: Option<String>,
+}
+
+/// The config the gameserver is running with, as of its latest reload. It's shared with the web
+/// server so reloads reach it too, and clones share the same config.
+#[derive(Clone)]
+pub struct SharedConfig(Arc<RwLock<Config>>);
+
+impl SharedConfig {
+    pub fn new(config: Config) -> Self {
+        SharedConfig(Arc::new(RwLock::new(config)))
+    }
+
+    /// A copy of the current config.
+    pub fn get(&self) -> Config {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, config: Config) {
+        *self.0.write().unwrap() = config;
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Open(io::Error),
+    Read(io::Error),
+    Parse(toml::de::Error),
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let mut file = File::open(path).map_err(ConfigError::Open)?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).map_err(ConfigError::Read)?;
+        toml::from_slice(&contents).map_err(ConfigError::Parse)
+    }
+
+    fn default_shutdown_timeout() -> u32 {
+        30
+    }
+
+    fn default_flag_retention() -> NonZeroU32 {
+        NonZeroU32::MIN
+    }
 }
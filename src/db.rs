@@ -1,17 +1,25 @@
+use std::collections::HashSet;
 use std::error::Error as StdError;
-use std::net::Ipv4Addr;
 use std::sync::Arc;
 
 use chrono::{DateTime, NaiveDateTime, Utc};
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
 use diesel::result::Error::{self as DieselError, NotFound, RollbackTransaction};
-use diesel_migrations::RunMigrationsError;
+use diesel_migrations::{MigrationConnection, RunMigrationsError};
 
-use crate::models::{CheckUp, Flag, NewFlag, NewTeam, Service, Team, Tick};
+use crate::config::TeamConfig;
+use crate::lifecycle::TeamState;
+use crate::models::{
+    CheckUp, Flag, NewAttempt, NewAuditEntry, NewFlag, NewTeam, Service, Team, Tick,
+};
+use crate::status::CheckerStatus;
 
 embed_migrations!("migrations");
 
+/// The versions and names of the embedded migrations, in order.
+const MIGRATIONS: &[(&str, &str)] = include!(concat!(env!("OUT_DIR"), "/migrations.rs"));
+
 macro_rules! match_flag {
     ($tick:expr, $team_id:expr, $service_name:expr) => {{
         use crate::schema::flags::dsl;
@@ -34,7 +42,10 @@ pub enum DbError {
     Migration(RunMigrationsError),
     Diesel(DieselError),
     InsertTeam(DieselError),
-    GetLastFlag(DieselError),
+    UpdateTeam(DieselError),
+    GetTeam(DieselError),
+    InsertAuditEntry(DieselError),
+    GetRecentFlags(DieselError),
     GetAllFlags(DieselError),
     GetAllTeams(DieselError),
     GetAllServices(DieselError),
@@ -42,6 +53,7 @@ pub enum DbError {
     InsertFlag(DieselError),
     InsertService(DieselError),
     InsertCheckup(DieselError),
+    InsertAttempt(DieselError),
     UpdateDefense(DieselError),
     LookupFlag(DieselError),
     UpdateClaim(DieselError),
@@ -67,13 +79,41 @@ impl Db {
         embedded_migrations::run(&conn.0).map_err(DbError::Migration)
     }
 
-    pub fn transaction<F, R>(&self, f: F) -> Result<R, DbError>
+    /// The migrations that haven't been run on the db yet, found by comparing the embedded ones
+    /// with the ones diesel has recorded. Nothing is written, so it's safe on a live db.
+    pub fn pending_migrations(&self) -> Result<Vec<String>, DbError> {
+        let conn = self.get_conn()?;
+        // a db that has never been migrated doesn't have the table yet
+        let migrated = diesel::select(diesel::dsl::sql::<diesel::sql_types::Bool>(
+            "to_regclass('__diesel_schema_migrations') IS NOT NULL",
+        ))
+        .get_result(&conn.0)
+        .map_err(DbError::Diesel)?;
+        let run = if migrated {
+            conn.0
+                .previously_run_migration_versions()
+                .map_err(DbError::Diesel)?
+        } else {
+            HashSet::new()
+        };
+        let pending = MIGRATIONS
+            .iter()
+            .filter(|(version, _)| !run.contains(*version))
+            .map(|(_, name)| (*name).to_owned())
+            .collect();
+        Ok(pending)
+    }
+
+    /// Runs `f` in a transaction, which is rolled back if it fails.
+    pub fn transaction<F, R, E>(&self, f: F) -> Result<R, E>
     where
-        F: FnOnce() -> Result<R, DbError>,
+        F: FnOnce(&Tx) -> Result<R, E>,
+        E: From<DbError>,
     {
-        let conn = self.get_conn()?;
+        let conn = self.get_conn().map_err(E::from)?;
+        let tx = Tx { conn: &conn.0 };
         let mut err = None;
-        let result = conn.0.transaction(|| match f() {
+        let result = conn.0.transaction(|| match f(&tx) {
             Ok(v) => Ok(v),
             Err(e) => {
                 err = Some(e);
@@ -86,76 +126,59 @@ impl Db {
         }
     }
 
-    pub fn clear_in_progress(&self, tick: i32) -> Result<(), DbError> {
-        let conn = self.get_conn()?;
-        self.transaction(|| {
-            {
-                use crate::schema::flags::dsl::{flags, in_progress, tick};
-                diesel::delete(flags.filter(in_progress.eq(true)))
-                    .execute(&conn.0)
-                    .map_err(DbError::Diesel)?;
-            }
-            {
-                use crate::schema::check_ups::dsl::{check_ups, in_progress};
-                diesel::delete(check_ups.filter(in_progress.eq(true)))
-                    .execute(&conn.0)
-                    .map_err(DbError::Diesel)?;
-            }
-            Ok(())
-        })
-    }
-
-    pub fn add_team(&self, team_id: i32, team_ip: Ipv4Addr) -> Result<(), DbError> {
+    /// Adds `team`, or brings its address and details in line with the config if it's already
+    /// there.
+    pub fn add_team(&self, team: &TeamConfig) -> Result<(), DbError> {
         use crate::schema::teams::dsl::{id, teams};
-        let conn = self.get_conn()?;
-        self.transaction(|| {
-            let team = match teams.filter(id.eq(team_id)).first::<Team>(&conn.0) {
+        let new_team = new_team(team, false);
+        self.transaction(|tx| {
+            let existing = match teams.filter(id.eq(team.id)).first::<Team>(tx.conn) {
                 Ok(v) => Some(v),
                 Err(NotFound) => None,
                 Err(err) => return Err(DbError::InsertTeam(err)),
             };
 
-            if team.is_none() {
+            if existing.is_none() {
                 // insert team
-                let new_team = NewTeam {
-                    id: team_id,
-                    ip: u32::from(team_ip) as i32,
-                };
-
                 use crate::schema::teams;
                 diesel::insert_into(teams::table)
                     .values(&new_team)
-                    .execute(&conn.0)
+                    .execute(tx.conn)
                     .map_err(DbError::InsertTeam)?;
+            } else {
+                diesel::update(teams.filter(id.eq(team.id)))
+                    .set(&new_team)
+                    .execute(tx.conn)
+                    .map_err(DbError::UpdateTeam)?;
             }
 
             Ok(())
         })
     }
 
+    /// Looks up a team, or returns `None` if there's no team with that id.
+    pub fn get_team(&self, team_id: i32) -> Result<Option<Team>, DbError> {
+        use crate::schema::teams::dsl::{id, teams};
+        let conn = self.get_conn()?;
+        match teams.filter(id.eq(team_id)).first(&conn.0) {
+            Ok(team) => Ok(Some(team)),
+            Err(NotFound) => Ok(None),
+            Err(err) => Err(DbError::GetTeam(err)),
+        }
+    }
+
+    /// Inserts the service, or updates its port and scores if it's already there.
     pub fn add_service(&self, new_service: &Service) -> Result<(), DbError> {
         use crate::schema::services::dsl::{name, services};
         let conn = self.get_conn()?;
-        self.transaction(|| {
-            let service = match services
-                .filter(name.eq(&new_service.name))
-                .first::<Service>(&conn.0)
-            {
-                Ok(v) => Some(v),
-                Err(NotFound) => None,
-                Err(err) => return Err(DbError::InsertService(err)),
-            };
-
-            if service.is_none() {
-                use crate::schema::services;
-                diesel::insert_into(services::table)
-                    .values(new_service)
-                    .execute(&conn.0)
-                    .map_err(DbError::InsertService)?;
-            }
-
-            Ok(())
-        })
+        diesel::insert_into(services)
+            .values(new_service)
+            .on_conflict(name)
+            .do_update()
+            .set(new_service)
+            .execute(&conn.0)
+            .map(|_| ())
+            .map_err(DbError::InsertService)
     }
 
     pub fn get_current_tick(&self) -> Result<(i32, NaiveDateTime), DbError> {
@@ -177,18 +200,17 @@ impl Db {
     pub fn bump_tick(&self) -> Result<(), DbError> {
         use crate::schema::flags::dsl::{flags, in_progress, tick as flag_tick};
         use crate::schema::tick::dsl::{current_tick, start_time, tick};
-        let conn = self.get_conn()?;
-        self.transaction(|| {
+        self.transaction(|tx| {
             // get the current tick number
             let tick_number = tick
-                .first::<Tick>(&conn.0)
+                .first::<Tick>(tx.conn)
                 .map(|row| row.current_tick)
                 .map_err(DbError::Diesel)?;
 
             // update all flags to not be in progress
             diesel::update(flags.filter(flag_tick.eq(tick_number)))
                 .set(in_progress.eq(false))
-                .execute(&conn.0)
+                .execute(tx.conn)
                 .map(|_| ())
                 .map_err(DbError::Tick)?;
 
@@ -198,7 +220,7 @@ impl Db {
                     current_tick.eq(tick_number + 1),
                     start_time.eq(Utc::now().naive_utc()),
                 ))
-                .execute(&conn.0)
+                .execute(tx.conn)
                 .map(|_| ())
                 .map_err(DbError::Tick)?;
 
@@ -209,24 +231,23 @@ impl Db {
     pub fn bump_checks(&self) -> Result<(), DbError> {
         use crate::schema::check_ups::dsl::{check_ups, in_progress};
         use crate::schema::tick::dsl::{current_check, start_time, tick};
-        let conn = self.get_conn()?;
-        self.transaction(|| {
+        self.transaction(|tx| {
             // get the current tick number
             let check_number = tick
-                .first::<Tick>(&conn.0)
+                .first::<Tick>(tx.conn)
                 .map(|row| row.current_check)
                 .map_err(DbError::Diesel)?;
 
             diesel::update(check_ups)
                 .set(in_progress.eq(false))
-                .execute(&conn.0)
+                .execute(tx.conn)
                 .map(|_| ())
                 .map_err(DbError::Tick)?;
 
             // update the tick number
             diesel::update(tick)
                 .set(current_check.eq(check_number + 1))
-                .execute(&conn.0)
+                .execute(tx.conn)
                 .map(|_| ())
                 .map_err(DbError::Tick)?;
 
@@ -267,7 +288,8 @@ impl Db {
         now: DateTime<Utc>,
         team_id: i32,
         service_name: impl AsRef<str>,
-        up: bool,
+        status: CheckerStatus,
+        message: Option<String>,
     ) -> Result<(), DbError> {
         use crate::schema::check_ups;
         let service_name = service_name.as_ref();
@@ -278,7 +300,8 @@ impl Db {
             team_id,
             service_name: service_name.to_owned(),
             in_progress: true,
-            up,
+            status,
+            message,
         };
         diesel::insert_into(check_ups::table)
             .values(&new_checkup)
@@ -287,11 +310,25 @@ impl Db {
             .map_err(DbError::InsertCheckup)
     }
 
-    pub fn get_last_flag(
+    pub fn insert_attempt(&self, new_attempt: &NewAttempt) -> Result<(), DbError> {
+        use crate::schema::attempts;
+        let conn = self.get_conn()?;
+        diesel::insert_into(attempts::table)
+            .values(new_attempt)
+            .execute(&conn.0)
+            .map(|_| ())
+            .map_err(DbError::InsertAttempt)
+    }
+
+    /// Flags for a team's service planted from tick `since` up to (but not including) tick
+    /// `before`, leaving out the ones that have already been lost or were never planted.
+    pub fn get_recent_flags(
         &self,
         team_id: i32,
         service_name: impl AsRef<str>,
-    ) -> Result<Flag, DbError> {
+        since: i32,
+        before: i32,
+    ) -> Result<Vec<Flag>, DbError> {
         use crate::schema::flags::dsl::{self, flags};
         let service_name = service_name.as_ref();
         let conn = self.get_conn()?;
@@ -299,11 +336,41 @@ impl Db {
             .filter(
                 dsl::team_id
                     .eq(team_id)
-                    .and(dsl::service_name.eq(service_name)),
+                    .and(dsl::service_name.eq(service_name))
+                    .and(dsl::tick.ge(since))
+                    .and(dsl::tick.lt(before))
+                    .and(dsl::lost.eq(false))
+                    .and(dsl::planted.eq(true)),
             )
             .order(dsl::tick.desc())
-            .first(&conn.0)
-            .map_err(DbError::GetLastFlag)
+            .load(&conn.0)
+            .map_err(DbError::GetRecentFlags)
+    }
+
+    /// Names of the services that already have a flag for this team in `tick`.
+    pub fn get_flagged_services(&self, tick: i32, team_id: i32) -> Result<Vec<String>, DbError> {
+        use crate::schema::flags::dsl::{self, flags};
+        let conn = self.get_conn()?;
+        flags
+            .filter(dsl::tick.eq(tick).and(dsl::team_id.eq(team_id)))
+            .select(dsl::service_name)
+            .load(&conn.0)
+            .map_err(DbError::GetAllFlags)
+    }
+
+    /// Names of the services that already have a check-up for this team in `check_number`.
+    pub fn get_checked_services(
+        &self,
+        check_number: i32,
+        team_id: i32,
+    ) -> Result<Vec<String>, DbError> {
+        use crate::schema::check_ups::dsl::{self, check_ups};
+        let conn = self.get_conn()?;
+        check_ups
+            .filter(dsl::id.eq(check_number).and(dsl::team_id.eq(team_id)))
+            .select(dsl::service_name)
+            .load(&conn.0)
+            .map_err(DbError::GetAllCheckup)
     }
 
     pub fn insert_flag(&self, new_flag: NewFlag) -> Result<(), DbError> {
@@ -316,40 +383,121 @@ impl Db {
             .map_err(DbError::InsertFlag)
     }
 
-    pub fn lookup_flag(&self, flag: impl AsRef<str>) -> Result<Flag, DbError> {
-        use crate::schema::flags::dsl::{self, flags};
-        let flag = flag.as_ref();
-        let conn = self.get_conn()?;
-        flags
-            .filter(dsl::flag.eq(flag))
-            .first(&conn.0)
-            .map_err(DbError::LookupFlag)
-    }
-
     pub fn update_defense(
         &self,
         tick: i32,
         team_id: i32,
         service_name: impl AsRef<str>,
-        result: bool,
+        status: CheckerStatus,
+        message: Option<String>,
     ) -> Result<(), DbError> {
-        use crate::schema::flags::dsl::{defended, flags, in_progress};
+        use crate::schema::flags::dsl::{self, flags, in_progress, lost};
         let service_name = service_name.as_ref();
-        let conn = self.get_conn()?;
-        diesel::update(flags.filter(match_flag!(tick, team_id, service_name)))
-            .set((defended.eq(result), in_progress.eq(false)))
-            .execute(&conn.0)
-            .map_err(DbError::UpdateDefense)
+        self.transaction(|tx| {
+            let flag = flags
+                .filter(match_flag!(tick, team_id, service_name))
+                .for_update()
+                .first::<Flag>(tx.conn)
+                .map_err(DbError::UpdateDefense)?;
+            let checked = flag.checked(status, message);
+
+            diesel::update(flags.filter(match_flag!(tick, team_id, service_name)))
+                .set((
+                    dsl::status.eq(checked.status),
+                    lost.eq(checked.lost),
+                    dsl::message.eq(checked.message),
+                    in_progress.eq(false),
+                ))
+                .execute(tx.conn)
+                .map_err(DbError::UpdateDefense)
+                .map(|_| ())
+        })
+    }
+}
+
+/// A transaction opened by [`Db::transaction`]. Everything done through it happens on the
+/// transaction's connection, so it's all committed or rolled back together.
+pub struct Tx<'a> {
+    conn: &'a PgConnection,
+}
+
+impl<'a> Tx<'a> {
+    /// Looks up a team like [`Db::get_team`], locking its row until the transaction ends.
+    pub fn get_team(&self, team_id: i32) -> Result<Option<Team>, DbError> {
+        use crate::schema::teams::dsl::{id, teams};
+        match teams.filter(id.eq(team_id)).for_update().first(self.conn) {
+            Ok(team) => Ok(Some(team)),
+            Err(NotFound) => Ok(None),
+            Err(err) => Err(DbError::GetTeam(err)),
+        }
+    }
+
+    pub fn insert_team(&self, team: &TeamConfig) -> Result<(), DbError> {
+        use crate::schema::teams;
+        diesel::insert_into(teams::table)
+            .values(&new_team(team, true))
+            .execute(self.conn)
+            .map(|_| ())
+            .map_err(DbError::InsertTeam)
+    }
+
+    pub fn set_team_state(&self, team_id: i32, team_state: TeamState) -> Result<(), DbError> {
+        use crate::schema::teams::dsl::{id, state, teams};
+        diesel::update(teams.filter(id.eq(team_id)))
+            .set(state.eq(team_state))
+            .execute(self.conn)
+            .map(|_| ())
+            .map_err(DbError::UpdateTeam)
+    }
+
+    /// Takes back every flag `team_id` claimed, returning how many there were.
+    pub fn void_captures(&self, team_id: i32) -> Result<usize, DbError> {
+        use crate::schema::flags::dsl::{claimed_by, flags};
+        diesel::update(flags.filter(claimed_by.eq(team_id)))
+            .set(claimed_by.eq(None::<i32>))
+            .execute(self.conn)
+            .map_err(DbError::UpdateClaim)
+    }
+
+    pub fn insert_audit_entry(&self, entry: &NewAuditEntry) -> Result<(), DbError> {
+        use crate::schema::audit_log;
+        diesel::insert_into(audit_log::table)
+            .values(entry)
+            .execute(self.conn)
             .map(|_| ())
+            .map_err(DbError::InsertAuditEntry)
+    }
+
+    /// Finds a flag that can be claimed. Flags that were never planted aren't found.
+    pub fn lookup_flag(&self, flag: impl AsRef<str>) -> Result<Flag, DbError> {
+        use crate::schema::flags::dsl::{self, flags};
+        let flag = flag.as_ref();
+        flags
+            .filter(dsl::flag.eq(flag).and(dsl::planted.eq(true)))
+            .first(self.conn)
+            .map_err(DbError::LookupFlag)
     }
 
     pub fn claim_flag(&self, flag: &Flag, claimed_by: i32) -> Result<(), DbError> {
         use crate::schema::flags::dsl::{self, flags};
-        let conn = self.get_conn()?;
         diesel::update(flags.filter(match_flag!(flag.tick, flag.team_id, &flag.service_name)))
             .set(dsl::claimed_by.eq(claimed_by))
-            .execute(&conn.0)
+            .execute(self.conn)
             .map_err(DbError::UpdateClaim)
             .map(|_| ())
     }
 }
+
+/// The row for `team` as it's described in the config.
+fn new_team(team: &TeamConfig, added_by_admin: bool) -> NewTeam {
+    NewTeam {
+        id: team.id,
+        ip: team.ip.to_string(),
+        name: team.name.clone(),
+        affiliation: team.affiliation.clone(),
+        country: team.country.clone(),
+        logo: team.logo.clone(),
+        nop: team.nop,
+        added_by_admin,
+    }
+}
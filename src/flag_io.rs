@@ -1,10 +1,16 @@
 use std::fs;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::ops::Range;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+use rand::seq::SliceRandom;
 use tokio::{prelude::*, timer::Delay};
 
+use crate::models::Flag;
+use crate::schedule::Schedule;
+use crate::shutdown::Shutdown;
 use crate::GameServer;
 
 pub fn calculate_round_length(tick_number: i32, interval_sec: u32) -> u64 {
@@ -14,13 +20,83 @@ pub fn calculate_round_length(tick_number: i32, interval_sec: u32) -> u64 {
     (delay * 60.0) as u64
 }
 
-pub fn ticker(gs: Arc<Mutex<GameServer>>) -> impl Future<Item = (), Error = ()> {
-    let (interval, log_directory) = {
-        let gs = gs.lock().unwrap();
-        let config = gs.get_config();
-        (config.check_period, config.log_directory.clone())
-    };
+/// The ticks whose flags are still checked during tick `tick`: the last `retention` ticks before
+/// it, which is what [`Db::get_recent_flags`](crate::Db::get_recent_flags) takes.
+///
+/// # Examples
+///
+/// ```
+/// # use std::num::NonZeroU32;
+/// # use gameserver::flag_io::flag_ticks;
+/// // only the flag planted in the previous tick
+/// assert_eq!(flag_ticks(5, NonZeroU32::new(1).unwrap()), 4..5);
+/// assert_eq!(flag_ticks(5, NonZeroU32::new(3).unwrap()), 2..5);
+/// // there are no flags from before the first tick
+/// assert_eq!(flag_ticks(1, NonZeroU32::new(3).unwrap()), -2..1);
+/// ```
+pub fn flag_ticks(tick: i32, retention: NonZeroU32) -> Range<i32> {
+    tick - retention.get() as i32..tick
+}
 
+/// Picks which of the recent flags to check this tick. The most recent flag is always checked;
+/// if `sample` is set, only a random selection of the older ones are checked along with it.
+///
+/// # Examples
+///
+/// ```
+/// # use std::num::NonZeroUsize;
+/// # use chrono::NaiveDateTime;
+/// # use gameserver::flag_io::pick_flags;
+/// # use gameserver::models::Flag;
+/// # let flag = |tick| Flag {
+/// #     tick,
+/// #     team_id: 1,
+/// #     service_name: "notes".to_owned(),
+/// #     flag: format!("flag{{{}}}", tick),
+/// #     flag_id: None,
+/// #     in_progress: false,
+/// #     claimed_by: None,
+/// #     created: NaiveDateTime::from_timestamp(0, 0),
+/// #     lost: false,
+/// #     status: None,
+/// #     message: None,
+/// #     planted: true,
+/// # };
+/// let ticks = |flags: Vec<Flag>| flags.iter().map(|flag| flag.tick).collect::<Vec<_>>();
+/// let recent = || vec![flag(2), flag(4), flag(3), flag(1)];
+/// let sample = |n| NonZeroUsize::new(n);
+///
+/// // without a sample, every flag is checked, newest first
+/// assert_eq!(ticks(pick_flags(recent(), None)), [4, 3, 2, 1]);
+/// // a sample as big as the flags, or bigger, checks all of them too
+/// assert_eq!(ticks(pick_flags(recent(), sample(4))), [4, 3, 2, 1]);
+/// assert_eq!(ticks(pick_flags(recent(), sample(9))), [4, 3, 2, 1]);
+/// // a sample of one only checks the newest flag
+/// assert_eq!(ticks(pick_flags(recent(), sample(1))), [4]);
+///
+/// // otherwise it's the newest flag and a few distinct older ones
+/// let picked = ticks(pick_flags(recent(), sample(3)));
+/// assert_eq!((picked.len(), picked[0]), (3, 4));
+/// assert!(picked[1] != picked[2] && picked[1..].iter().all(|&tick| tick < 4));
+/// assert!(pick_flags(Vec::new(), sample(2)).is_empty());
+/// ```
+pub fn pick_flags(mut flags: Vec<Flag>, sample: Option<NonZeroUsize>) -> Vec<Flag> {
+    flags.sort_unstable_by_key(|flag| -flag.tick);
+    if let Some(sample) = sample.map(NonZeroUsize::get) {
+        if flags.len() > sample {
+            let mut older = flags.split_off(1);
+            older.shuffle(&mut rand::thread_rng());
+            older.truncate(sample - 1);
+            flags.extend(older);
+        }
+    }
+    flags
+}
+
+pub fn ticker(
+    gs: Arc<Mutex<GameServer>>,
+    shutdown: Shutdown,
+) -> impl Future<Item = (), Error = ()> {
     // get the latest tick number
     let (tick_number, _) = {
         let gs = gs.lock().unwrap();
@@ -35,15 +111,27 @@ pub fn ticker(gs: Arc<Mutex<GameServer>>) -> impl Future<Item = (), Error = ()>
         round_length: u64,
         tick_number: i32,
         log_directory: impl AsRef<Path>,
+        shutdown: Shutdown,
     ) -> impl Future<Item = (), Error = ()> + Send + Sync {
         let log_directory = log_directory.as_ref();
 
         // get teams
-        let teams = {
+        let (mut teams, spread, services) = {
             let gs = gs.lock().unwrap();
-            gs.get_teams()
+            (gs.get_teams(), gs.get_config().spread, gs.service_count())
         };
 
+        // start each team's jobs at a different time every tick, and queue the teams for checker
+        // slots in a different order, so the same teams don't always get the first slots
+        let schedule = Schedule::new(
+            Duration::from_secs(round_length),
+            spread,
+            teams.len(),
+            services,
+        );
+        info!("tick {}: schedule seed {}", tick_number, schedule.seed());
+        schedule.shuffle(&mut teams);
+
         let mut fut: Box<Future<Item = (), Error = ()> + Send + Sync> = Box::new(future::ok(()));
         for team in teams {
             // log dir
@@ -70,18 +158,28 @@ pub fn ticker(gs: Arc<Mutex<GameServer>>) -> impl Future<Item = (), Error = ()>
             fut = Box::new(
                 fut.join(gs.each_team(
                     db,
+                    schedule,
                     tick_number,
-                    team.id,
-                    team.ip,
+                    team,
                     has_prev,
                     get_log_dir,
                     set_log_dir,
+                    shutdown.clone(),
                 ))
                 .map(|_| ()),
             );
         }
 
+        let interrupted = shutdown.clone();
         fut = Box::new(fut.and_then(move |_| {
+            // jobs that were skipped for the shutdown are run when the tick is resumed
+            if interrupted.is_requested() {
+                info!(
+                    "tick {} was cut short, it's resumed on restart",
+                    tick_number
+                );
+                return Ok(());
+            }
             let gs = gs.clone();
             let gs = gs.lock().unwrap();
             let db = gs.get_db();
@@ -93,15 +191,35 @@ pub fn ticker(gs: Arc<Mutex<GameServer>>) -> impl Future<Item = (), Error = ()>
         // delay
         Box::new(
             fut.join(
-                Delay::new(Instant::now() + Duration::from_secs(round_length)).map_err(|err| {
-                    error!("Timer error: {}", err);
-                }),
+                Delay::new(Instant::now() + Duration::from_secs(round_length))
+                    .map_err(|err| {
+                        error!("Timer error: {}", err);
+                    })
+                    // don't sit out the rest of the round if we're shutting down
+                    .select(shutdown.wait())
+                    .map(|_| ())
+                    .map_err(|_| ()),
             )
             .map(|_| ()),
         )
     }
 
-    stream::unfold((tick_number, false), move |(tick_number, has_prev)| {
+    // a restart resumes the current tick, so the previous tick's flags are still there to check
+    let has_prev = tick_number > 0;
+
+    stream::unfold((tick_number, has_prev), move |(tick_number, has_prev)| {
+        if shutdown.is_requested() {
+            info!("shutdown requested, not starting tick {}", tick_number);
+            return None;
+        }
+
+        // apply any pending reload between rounds
+        let (interval, log_directory) = {
+            let mut gs = gs.lock().unwrap();
+            gs.reload_if_requested();
+            let config = gs.get_config();
+            (config.check_period, config.log_directory.clone())
+        };
         let round_length = calculate_round_length(tick_number, interval);
         info!(
             "=== TICK {} (has_prev={}): this round will last up to {}s",
@@ -113,7 +231,8 @@ pub fn ticker(gs: Arc<Mutex<GameServer>>) -> impl Future<Item = (), Error = ()>
                 gs.clone(),
                 round_length,
                 tick_number,
-                log_directory.to_path_buf(),
+                log_directory,
+                shutdown.clone(),
             )
             .map(move |_| ((), (tick_number + 1, true))),
         )
@@ -1,25 +1,41 @@
+use std::collections::HashMap;
 use std::ffi::OsString;
 use std::fs;
 use std::io;
-use std::net::Ipv4Addr;
-use std::path::Path;
+use std::iter;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use chrono::{DateTime, Utc};
-use rand::RngCore;
+use futures::future::Either;
 use tokio::{prelude::*, timer::Delay};
 
+use crate::checker::{Action, CheckContext, Registry};
+use crate::config::SharedConfig;
 use crate::db::{Db, DbError};
+use crate::flag_io::{flag_ticks, pick_flags};
 use crate::key::generate_flag;
-use crate::models::{self, Flag, NewFlag};
+use crate::lifecycle::TeamState;
+use crate::limit::{Limiter, Slot};
+use crate::models::{self, Flag, NewAttempt, NewFlag};
+use crate::reload::Reload;
+use crate::retry::{attempt_context, retry};
+use crate::schedule::{self, Schedule};
 use crate::service::{Service, ServiceError};
-use crate::{Config, TeamConfig};
+use crate::shutdown::Shutdown;
+use crate::status::CheckerStatus;
+use crate::{Config, ConfigError, TeamConfig};
 
 pub struct GameServer {
     db: Db,
     config: Config,
+    shared_config: SharedConfig,
     services: Vec<Arc<Mutex<Service>>>,
+    limiter: Option<Limiter>,
+    config_path: PathBuf,
+    reload: Reload,
+    checkers: Registry,
 }
 
 #[derive(Debug)]
@@ -28,15 +44,62 @@ pub enum GameServerError {
     ListServices(io::Error),
     ReadEntry(io::Error),
     Delay(tokio::timer::Error),
+    /// The job hadn't started its checker when a shutdown was requested, so it's left for the
+    /// gameserver to pick back up when it next starts.
+    ShutDown,
+    Limit(tokio_sync::semaphore::AcquireError),
     Service(ServiceError),
     GetFlag(ServiceError),
     CheckUp(ServiceError),
     SetFlag(ServiceError),
+    Noise(ServiceError),
     OsString(OsString),
+    Config(ConfigError),
+}
+
+impl GameServerError {
+    /// The verdict to record for a job that failed with this error. Anything that went wrong on
+    /// our side rather than in the checker is an `Error`.
+    fn status(&self) -> CheckerStatus {
+        match self {
+            GameServerError::Service(err)
+            | GameServerError::GetFlag(err)
+            | GameServerError::CheckUp(err)
+            | GameServerError::SetFlag(err)
+            | GameServerError::Noise(err) => err.status(),
+            _ => CheckerStatus::Error,
+        }
+    }
+
+    /// Whether trying the job again could help.
+    fn is_retryable(&self) -> bool {
+        !matches!(self, GameServerError::ShutDown)
+    }
+
+    /// The public message the checker printed before failing, if it got that far.
+    fn public_message(&self) -> Option<String> {
+        match self {
+            GameServerError::Service(err)
+            | GameServerError::GetFlag(err)
+            | GameServerError::CheckUp(err)
+            | GameServerError::SetFlag(err)
+            | GameServerError::Noise(err) => err.public_message(),
+            _ => None,
+        }
+    }
 }
 
 impl GameServer {
-    pub fn new(config: Config) -> Result<Self, GameServerError> {
+    pub fn new(config: Config, config_path: impl AsRef<Path>) -> Result<Self, GameServerError> {
+        GameServer::with_checkers(config, config_path, Registry::new())
+    }
+
+    /// Like [`GameServer::new`], but services may also use the native checkers in `checkers`.
+    pub fn with_checkers(
+        config: Config,
+        config_path: impl AsRef<Path>,
+        checkers: Registry,
+    ) -> Result<Self, GameServerError> {
         // create the log directory if it doesn't exist
         if !config.log_directory.exists() {
             fs::create_dir_all(&config.log_directory).expect("failed to create log directory");
@@ -45,77 +108,12 @@ impl GameServer {
         // connect to db
         let db = Db::connect(&config.db).map_err(GameServerError::Db)?;
 
-        // clear in-progress flags
-        let (current_tick, _) = db.get_current_tick().expect("failed to get current tick");
-        db.clear_in_progress(current_tick)
-            .map_err(GameServerError::Db)?;
-
         // load teams into db
         for team in &config.teams {
-            db.add_team(team.id, team.ip).map_err(GameServerError::Db)?;
-        }
-
-        // list the directory
-        let read_dir = fs::read_dir(&config.services_dir).map_err(GameServerError::ListServices)?;
-        let mut services = Vec::new();
-        for entry in read_dir {
-            let entry = match entry {
-                Ok(entry) => entry,
-                Err(err) => {
-                    services.push((String::new(), Err(GameServerError::ReadEntry(err))));
-                    continue;
-                }
-            };
-
-            let name = match entry.file_name().into_string() {
-                Ok(name) => name,
-                Err(name) => {
-                    services.push((String::new(), Err(GameServerError::OsString(name))));
-                    continue;
-                }
-            };
-
-            // ignore non-directories
-            if !entry
-                .file_type()
-                .map_err(GameServerError::ReadEntry)?
-                .is_dir()
-            {
-                continue;
-            }
-
-            // ignore directories starting with .
-            if name.starts_with('.') {
-                continue;
-            }
-
-            // ignore files listed explicitly in ignores
-            if config.ignores.contains(&name) {
-                continue;
-            }
-
-            let path = entry.path();
-            let service = match Service::load_from_dir(&config, &name, &path)
-                .map_err(GameServerError::Service)
-                .and_then(|service| {
-                    db.add_service(&models::Service {
-                        name: name.clone(),
-                        port: service.config.port as i32,
-                        atk_score: service.config.atk_score as i32,
-                        def_score: service.config.def_score as i32,
-                        up_score: service.config.up_score as i32,
-                    })
-                    .map(|_| service)
-                    .map_err(GameServerError::Db)
-                }) {
-                Ok(service) => (name, Ok(service)),
-                Err(err) => (name, Err(err)),
-            };
-
-            services.push(service);
+            db.add_team(team).map_err(GameServerError::Db)?;
         }
 
-        let services = services
+        let services = load_services(&config, &checkers, &db)?
             .into_iter()
             .filter_map(|(name, service)| match service {
                 Ok(service) => Some(Arc::new(Mutex::new(service))),
@@ -125,68 +123,285 @@ impl GameServer {
                 }
             })
             .collect();
+        let limiter = config.max_checkers.map(Limiter::new);
         let gameserver = GameServer {
             db,
+            shared_config: SharedConfig::new(config.clone()),
             config,
             services,
+            limiter,
+            config_path: config_path.as_ref().to_path_buf(),
+            reload: Reload::new(),
+            checkers,
         };
         Ok(gameserver)
     }
 
+    /// Returns a handle that schedules a reload of the config and services.
+    pub fn get_reload(&self) -> Reload {
+        self.reload.clone()
+    }
+
+    /// Returns a handle to the config that follows reloads.
+    pub fn get_shared_config(&self) -> SharedConfig {
+        self.shared_config.clone()
+    }
+
+    /// Applies a scheduled reload, if there is one. The flag ticker calls this between ticks, so
+    /// a reload never changes anything in the middle of a tick. Check rounds that are already
+    /// running finish with the services they started with.
+    pub fn reload_if_requested(&mut self) {
+        if !self.reload.take() {
+            return;
+        }
+
+        info!("reloading {}", self.config_path.display());
+        if let Err(err) = self.reload() {
+            error!("reload failed, keeping the current config: {:?}", err);
+        }
+    }
+
+    fn reload(&mut self) -> Result<(), GameServerError> {
+        let config = Config::load(&self.config_path).map_err(GameServerError::Config)?;
+        if config.db != self.config.db || config.bind_addr != self.config.bind_addr {
+            warn!("changes to db and bind_addr only take effect after a restart");
+        }
+
+        for team in &config.teams {
+            self.db.add_team(team).map_err(GameServerError::Db)?;
+        }
+
+        let mut old_services = self
+            .services
+            .drain(..)
+            .map(|service| {
+                let name = service.lock().unwrap().name.clone();
+                (name, service)
+            })
+            .collect::<HashMap<_, _>>();
+        for (name, service) in load_services(&config, &self.checkers, &self.db)? {
+            let old_service = old_services.remove(&name);
+            let service = match (service, old_service) {
+                (Ok(service), Some(old_service)) => {
+                    let unchanged = {
+                        let old_service = old_service.lock().unwrap();
+                        old_service.config == service.config
+                            && old_service.base_dir == service.base_dir
+                            && old_service.timeout == service.timeout
+                            && old_service.delay == service.delay
+                            && old_service.log_max_bytes == service.log_max_bytes
+                            && old_service.secret_key == service.secret_key
+                            && old_service.limits == service.limits
+                    };
+                    if unchanged {
+                        old_service
+                    } else {
+                        info!("reload: updated service {}", name);
+                        Arc::new(Mutex::new(service))
+                    }
+                }
+                (Ok(service), None) => {
+                    info!("reload: added service {}", name);
+                    Arc::new(Mutex::new(service))
+                }
+                (Err(err), Some(old_service)) => {
+                    error!(
+                        "Error reloading {}, keeping the old version: {:?}",
+                        name, err
+                    );
+                    old_service
+                }
+                (Err(err), None) => {
+                    error!("Error loading {}: {:?}", name, err);
+                    continue;
+                }
+            };
+            self.services.push(service);
+        }
+        for name in old_services.keys() {
+            info!("reload: removed service {}", name);
+        }
+
+        if config.max_checkers != self.config.max_checkers {
+            self.limiter = config.max_checkers.map(Limiter::new);
+        }
+        self.shared_config.set(config.clone());
+        self.config = config;
+        Ok(())
+    }
+
     pub fn get_config(&self) -> &Config {
         &self.config
     }
 
+    /// How many services are loaded.
+    pub fn service_count(&self) -> usize {
+        self.services.len()
+    }
+
+    /// The teams to play this round: the ones in the config and the ones added to the running
+    /// game, leaving out any that are suspended or disqualified.
     pub fn get_teams(&self) -> Vec<TeamConfig> {
-        self.config.teams.clone()
+        let stored = match self.db.get_all_teams() {
+            Ok(stored) => stored,
+            Err(err) => {
+                error!(
+                    "couldn't read the teams, playing the ones in the config: {:?}",
+                    err
+                );
+                return self.config.teams.clone();
+            }
+        };
+        let states = stored
+            .iter()
+            .map(|team| (team.id, team.state))
+            .collect::<HashMap<_, _>>();
+        let configured = self.config.teams.iter().cloned();
+        let added = stored
+            .into_iter()
+            .filter(|team| team.added_by_admin)
+            .filter(|team| !self.config.teams.iter().any(|other| other.id == team.id))
+            .filter_map(added_team);
+        configured
+            .chain(added)
+            .filter(|team| {
+                states.get(&team.id).copied().unwrap_or(TeamState::Active) == TeamState::Active
+            })
+            .collect()
     }
 
     pub fn get_db(&self) -> Db {
         self.db.clone()
     }
 
+    /// Returns the services that don't appear in `done`. When the gameserver is restarted
+    /// partway through a round, this is used to only run the jobs that didn't finish.
+    fn pending_services(
+        &self,
+        done: Result<Vec<String>, DbError>,
+        what: &str,
+        team_id: i32,
+    ) -> Vec<Arc<Mutex<Service>>> {
+        let done = match done {
+            Ok(done) => done,
+            Err(err) => {
+                error!(
+                    "couldn't look up finished {} jobs for team_id={}: {}",
+                    what, team_id, err
+                );
+                Vec::new()
+            }
+        };
+        if !done.is_empty() {
+            info!(
+                "resuming {} team_id={}: skipping finished services {:?}",
+                what, team_id, done
+            );
+        }
+
+        schedule::pending(
+            &self.services,
+            |service| service.lock().unwrap().name.clone(),
+            &done,
+        )
+    }
+
+    /// Runs a check round against `team`: every service's check_up, along with its noise
+    /// actions. Each run starts at its own point in the `schedule`, and the round only counts as
+    /// up if all of them succeed. Services whose runs hadn't all started by a `shutdown` aren't
+    /// recorded, so they're run again when the gameserver next starts.
+    #[allow(clippy::too_many_arguments)]
     pub fn check_up(
         &self,
         db: Db,
+        schedule: Schedule,
         check_number: i32,
         now: DateTime<Utc>,
-        team_id: i32,
-        target: Ipv4Addr,
+        team: TeamConfig,
         log_dir: impl AsRef<Path>,
+        shutdown: Shutdown,
     ) -> impl Future<Item = (), Error = ()> + Send + Sync {
-        let mut rng = rand::thread_rng();
-        let services = self.services.clone();
-        let delay = self.config.delay;
+        let team_id = team.id;
+        let services = self.pending_services(
+            db.get_checked_services(check_number, team_id),
+            "check_up",
+            team_id,
+        );
+        let limiter = self.limiter.clone();
         let log_dir = log_dir.as_ref().to_path_buf();
 
         future::join_all(services.into_iter().map(move |service_mux| {
-            let service_mux2 = service_mux.clone();
-            let service = service_mux.lock().unwrap();
-            let name = service.name.clone();
-            let log_dir = log_dir.clone();
-
-            // choose a random delay
-            let delay = rng.next_u32() % delay;
-            let delay_timer = Delay::new(Instant::now() + Duration::from_secs(delay.into()));
-            let fut: Box<Future<Item = (), Error = GameServerError> + Send + Sync> =
-                Box::new(delay_timer.map(|_| ()).map_err(GameServerError::Delay));
-            let db = db.clone();
-
-            let svc_name = name.clone();
-            let insert_checkup = move |result: Result<_, _>| {
-                db.insert_checkup(check_number, now, team_id, svc_name, result.is_ok())
-                    .map_err(GameServerError::Db)
+            let (name, noise) = {
+                let service = service_mux.lock().unwrap();
+                (service.name.clone(), service.config.noise.clone())
             };
-            let svc_name = name.clone();
-
-            fut.and_then(move |_| {
-                let service = service_mux2.lock().unwrap();
-                info!("check_up service={} team_id={}", name, team_id);
-                service
-                    .check_up(target, log_dir)
-                    .map_err(GameServerError::CheckUp)
+            let log_dir = log_dir.join(&name);
+
+            let actions = iter::once(Action::CheckUp).chain(noise.into_iter().map(Action::Noise));
+            let runs = actions
+                .map(|action| {
+                    let log_dir = match action {
+                        Action::Noise(noise) => log_dir.join(noise.as_str()),
+                        _ => log_dir.clone(),
+                    };
+                    let run = Run {
+                        service_mux: service_mux.clone(),
+                        limiter: limiter.clone(),
+                        db: db.clone(),
+                        schedule,
+                        action,
+                        team: team.clone(),
+                        tick: check_number,
+                        log_dir,
+                        shutdown: shutdown.clone(),
+                    };
+                    run.check().then(Ok::<_, GameServerError>)
+                })
+                .collect::<Vec<_>>();
+
+            let (db, nop) = (db.clone(), team.nop);
+            future::join_all(runs).and_then(move |results| {
+                if results
+                    .iter()
+                    .any(|result| matches!(result, Err(GameServerError::ShutDown)))
+                {
+                    info!(
+                        "shutdown requested, leaving check_up service={} team_id={} for the restart",
+                        name, team_id
+                    );
+                    return Ok(());
+                }
+
+                // the verdict is the first failure, preferring ones that count against the team,
+                // and check_up's message is kept if everything worked
+                let mut results = results.into_iter();
+                let check_up = results.next().expect("check_up always runs");
+                let result = results.fold(check_up, |verdict, result| {
+                    let worse = match (&verdict, &result) {
+                        (_, Ok(_)) => false,
+                        (Ok(_), Err(_)) => true,
+                        (Err(verdict), Err(err)) => {
+                            !verdict.status().counts_against() && err.status().counts_against()
+                        }
+                    };
+                    if worse {
+                        result
+                    } else {
+                        verdict
+                    }
+                });
+
+                let (status, message) = match result {
+                    Ok(message) => (CheckerStatus::Ok, message),
+                    Err(err) => {
+                        debug!("check_up service={} team_id={} => {:?}", name, team_id, err);
+                        (err.status(), err.public_message())
+                    }
+                };
+                warn_if_nop(nop, team_id, Action::CheckUp, &name, status);
+                db.insert_checkup(check_number, now, team_id, name, status, message)
+                        .map_err(GameServerError::Db)
             })
-            .then(insert_checkup)
         }))
         .map(|_| ())
         .map_err(|err| {
@@ -194,76 +409,147 @@ impl GameServer {
         })
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn each_team(
         &self,
         db: Db,
+        schedule: Schedule,
         tick: i32,
-        team_id: i32,
-        target: Ipv4Addr,
+        team: TeamConfig,
         has_prev: bool,
         get_log_dir: impl AsRef<Path>,
         set_log_dir: impl AsRef<Path>,
+        shutdown: Shutdown,
     ) -> impl Future<Item = (), Error = ()> + Send + Sync {
-        let services = self.services.clone();
-        let delay = self.config.delay;
+        let team_id = team.id;
+        let services = self
+            .pending_services(db.get_flagged_services(tick, team_id), "tick", team_id)
+            .into_iter()
+            .filter(|service| service.lock().unwrap().has_flags());
+        let flag_retention = self.config.flag_retention;
+        let flag_sample = self.config.flag_sample;
+        let limiter = self.limiter.clone();
         let get_log_dir = get_log_dir.as_ref().to_path_buf();
         let set_log_dir = set_log_dir.as_ref().to_path_buf();
 
         future::join_all(services.into_iter().map(move |service_mux| {
-            let mut rng = rand::thread_rng();
             let db = db.clone();
-            let service_name = {
+            let (service_name, service_limiter, delay) = {
                 let service = service_mux.lock().unwrap();
-                service.name.clone()
+                let window = schedule.window(&service, limiter.as_ref());
+                let delay = schedule.offset(window, team_id, &service.name, "tick");
+                (service.name.clone(), service.limiter.clone(), delay)
             };
             let set_log_dir = set_log_dir.join(&service_name);
             let get_log_dir = get_log_dir.join(&service_name);
 
-            // choose a random delay
-            let delay = rng.next_u32() % delay;
-            let delay_timer = Delay::new(Instant::now() + Duration::from_secs(delay.into()));
             let mut fut2: Box<Future<Item = (), Error = GameServerError> + Send + Sync> =
-                Box::new(delay_timer.map(|_| ()).map_err(GameServerError::Delay));
+                Box::new(wait_turn(delay, &shutdown));
 
-            // there's no previous flag if the tick is 0
+            // check the flags planted in the last few ticks; there aren't any in tick 0
             if has_prev {
-                let last_flag = future::result(db.get_last_flag(team_id, &service_name))
-                    .map_err(GameServerError::Db);
+                let ticks = flag_ticks(tick, flag_retention);
+                let flags = future::result(db.get_recent_flags(
+                    team_id,
+                    &service_name,
+                    ticks.start,
+                    ticks.end,
+                ))
+                .map_err(GameServerError::Db)
+                .map(move |flags| pick_flags(flags, flag_sample));
                 let svc = service_mux.clone();
-                let get_flag = move |last_flag: Flag| {
-                    let service = svc.lock().unwrap();
-                    let info = format!(
-                        "get_flag tick={} service={} team_id={} flag_id={:?}",
-                        tick, service.name, team_id, last_flag.flag_id
-                    );
-                    info!("{}", info);
-
-                    service
-                        .get_flag(target, last_flag.flag_id.clone(), get_log_dir)
-                        .map(move |result| {
-                            debug!(" {} => {:?}", info, result);
-                            (last_flag, result)
-                        })
-                        .map_err(GameServerError::GetFlag)
-                };
+                let team = team.clone();
+                let limiter = limiter.clone();
+                let service_limiter = service_limiter.clone();
                 let db = db.clone();
-                let db2 = db.clone();
-                let service_name = service_name.clone();
-                let svc_name = service_name.clone();
-                fut2 = Box::new(
-                    fut2.and_then(|_| last_flag)
-                        .and_then(get_flag)
-                        .map(move |(last_flag, flag)| (last_flag.tick, last_flag.flag == flag))
-                        .and_then(move |(tick, result)| {
-                            db.update_defense(tick, team_id, service_name, result)
+                let shutdown = shutdown.clone();
+                let get_flags = move |flags: Vec<Flag>| {
+                    stream::iter_ok(flags).for_each(move |flag| {
+                        let svc = svc.clone();
+                        let team = team.clone();
+                        let nop = team.nop;
+                        let db = db.clone();
+                        let attempts_db = db.clone();
+                        let (limiter, service_limiter) = (limiter.clone(), service_limiter.clone());
+                        let shutdown = shutdown.clone();
+                        let log_dir = get_log_dir.join(format!("flag_{:03}", flag.tick));
+                        let policy = svc.lock().unwrap().config.retry.policy(Action::GetFlag);
+                        let flag2 = flag.clone();
+                        let retryable = GameServerError::is_retryable;
+                        let get_flag = retry(policy, retryable, move |attempt, deadline| {
+                            let (svc, team) = (svc.clone(), team.clone());
+                            let (db, log_dir) = (attempts_db.clone(), log_dir.clone());
+                            let flag = flag2.clone();
+                            let slots = acquire_slots(
+                                limiter.clone(),
+                                service_limiter.clone(),
+                                shutdown.clone(),
+                            );
+                            slots.and_then(
+                                move |slots| {
+                                    let service = svc.lock().unwrap();
+                                    let info = format!(
+                                        "get_flag tick={} service={} team_id={} flag_tick={} flag_id={:?} attempt={}",
+                                        tick, service.name, team_id, flag.tick, flag.flag_id, attempt
+                                    );
+                                    info!("{} queued={:?}", info, queued(&slots));
+
+                                    let ctx = service
+                                        .context(Action::GetFlag, &team, flag.tick, log_dir);
+                                    let ctx = attempt_context(ctx, attempt, deadline);
+                                    let record = attempt_recorder(db, &ctx, attempt);
+                                    service.get_flag(ctx, flag.flag_id.clone()).then(move |result| {
+                                        drop(slots);
+                                        debug!(" {} => {:?}", info, result);
+                                        // a wrong flag is worth retrying too
+                                        let result = match result {
+                                            Ok((ref found, message)) if *found == flag.flag => {
+                                                Ok(message)
+                                            }
+                                            Ok((_, message)) => Err(ServiceError::Verdict(
+                                                CheckerStatus::Corrupt,
+                                                message,
+                                            )),
+                                            Err(err) => Err(err),
+                                        }
+                                        .map_err(GameServerError::GetFlag);
+                                        match &result {
+                                            Ok(message) => record(CheckerStatus::Ok, message.clone()),
+                                            Err(err) => record(err.status(), err.public_message()),
+                                        }
+                                        result
+                                    })
+                                },
+                            )
+                        });
+                        get_flag.then(move |result| {
+                            let (status, message) = match result {
+                                // the flag is checked again after the restart
+                                Err(GameServerError::ShutDown) => {
+                                    return Err(GameServerError::ShutDown);
+                                }
+                                Ok(message) => (CheckerStatus::Ok, message),
+                                Err(err) => {
+                                    warn!(
+                                        "error with service={} team_id={} flag_tick={}: {:?}",
+                                        flag.service_name, team_id, flag.tick, err
+                                    );
+                                    (err.status(), err.public_message())
+                                }
+                            };
+                            warn_if_nop(nop, team_id, Action::GetFlag, &flag.service_name, status);
+                            db.update_defense(
+                                    flag.tick,
+                                    team_id,
+                                    &flag.service_name,
+                                    status,
+                                    message,
+                                )
                                 .map_err(GameServerError::Db)
                         })
-                        .or_else(move |err| {
-                            db2.update_defense(tick, team_id, svc_name, false)
-                                .map_err(GameServerError::Db)
-                                .and_then(|_| Err(err))
-                        }),
-                );
+                    })
+                };
+                fut2 = Box::new(fut2.and_then(|_| flags).and_then(get_flags));
             };
 
             // set the new flag
@@ -271,50 +557,402 @@ impl GameServer {
             let flag2 = flag.clone();
 
             let db = db.clone();
+            let attempts_db = db.clone();
             let svc_name = service_name.clone();
 
             let service_name = service_name.clone();
             let svc_name2 = service_name.clone();
             let svc_mux = service_mux.clone();
-            fut2.then(move |result| {
-                let service = svc_mux.lock().unwrap();
-                let info = format!(
-                    "set_flag tick={} service={} team_id={}",
-                    tick, service.name, team_id
-                );
-                info!("{}", info);
-
-                let svc_name = service_name.clone();
-                let insert_flag = move |flag_id| {
-                    debug!("  {} => {:?}", info, flag_id);
-                    let new_flag = NewFlag {
-                        flag: flag2.clone(),
-                        flag_id,
-                        team_id,
+            let policy = service_mux.lock().unwrap().config.retry.policy(Action::SetFlag);
+            let (limiter, team, shutdown) = (limiter.clone(), team.clone(), shutdown.clone());
+            let nop = team.nop;
+            let retryable = GameServerError::is_retryable;
+            let set_flag = retry(policy, retryable, move |attempt, deadline| {
+                let (svc_mux, team) = (svc_mux.clone(), team.clone());
+                let (db, set_log_dir, flag) = (attempts_db.clone(), set_log_dir.clone(), flag.clone());
+                let slots = acquire_slots(limiter.clone(), service_limiter.clone(), shutdown.clone());
+                slots.and_then(move |slots| {
+                    let service = svc_mux.lock().unwrap();
+                    info!(
+                        "set_flag tick={} service={} team_id={} attempt={} queued={:?}",
                         tick,
-                        service_name: svc_name.clone(),
+                        service.name,
+                        team_id,
+                        attempt,
+                        queued(&slots)
+                    );
+                    let ctx = service.context(Action::SetFlag, &team, tick, set_log_dir);
+                    let ctx = attempt_context(ctx, attempt, deadline);
+                    let record = attempt_recorder(db, &ctx, attempt);
+                    service
+                        .set_flag(ctx, flag)
+                        .map_err(GameServerError::SetFlag)
+                        .then(move |result| {
+                            drop(slots);
+                            match &result {
+                                Ok((_, message)) => record(CheckerStatus::Ok, message.clone()),
+                                Err(err) => record(err.status(), err.public_message()),
+                            }
+                            result
+                        })
+                })
+            });
+            fut2.then(|result| set_flag.then(|set_flag| Ok::<_, GameServerError>((result, set_flag))))
+                .and_then(move |(result, set_flag)| {
+                    // without a flag for this tick, the whole job is run again after the restart
+                    if let Err(GameServerError::ShutDown) = set_flag {
+                        return Either::A(future::err(GameServerError::ShutDown));
+                    }
+                    let status = match &set_flag {
+                        Ok(_) => CheckerStatus::Ok,
+                        Err(err) => err.status(),
+                    };
+                    warn_if_nop(nop, team_id, Action::SetFlag, &service_name, status);
+                    let info = format!(
+                        "set_flag tick={} service={} team_id={}",
+                        tick, service_name, team_id
+                    );
+
+                    // a flag that set_flag failed on is still recorded, but as not planted
+                    let svc_name = service_name.clone();
+                    let insert_flag = move |flag_id, message, failed: Option<CheckerStatus>| {
+                        debug!("  {} => {:?}", info, flag_id);
+                        let new_flag = NewFlag {
+                            flag: flag2.clone(),
+                            flag_id,
+                            team_id,
+                            tick,
+                            service_name: svc_name.clone(),
+                            message,
+                            planted: failed.is_none(),
+                            status: failed,
+                        };
+                        db.insert_flag(new_flag).map_err(GameServerError::Db)
                     };
-                    db.insert_flag(new_flag).map_err(GameServerError::Db)
-                };
 
-                let svc_name = service_name.clone();
-                let set_flag = service
-                    .set_flag(target, flag, set_log_dir)
-                    .map_err(GameServerError::SetFlag);
-                set_flag
-                    .and_then(insert_flag.clone())
-                    .or_else(move |err| insert_flag(None).and_then(|_| Err(err)))
-                    .and_then(|_| future::result(result))
-                    .or_else(move |err2| {
-                        warn!(
+                    let svc_name = service_name.clone();
+                    let insert_failed = insert_flag.clone();
+                    let insert = future::result(set_flag)
+                        .and_then(move |(flag_id, message)| insert_flag(flag_id, message, None))
+                        .or_else(move |err: GameServerError| {
+                            insert_failed(None, err.public_message(), Some(err.status()))
+                                .and_then(|_| Err(err))
+                        })
+                        .and_then(|_| future::result(result))
+                        .or_else(move |err2| {
+                            warn!(
+                                "error with service={} team_id={}: {:?}",
+                                svc_name, team_id, err2
+                            );
+                            Ok(())
+                        });
+                    Either::B(insert)
+                })
+                .or_else(move |err| {
+                    match err {
+                        GameServerError::ShutDown => info!(
+                            "shutdown requested, leaving tick={} service={} team_id={} for the restart",
+                            tick, svc_name2, team_id
+                        ),
+                        err => warn!(
                             "error with service={} team_id={}: {:?}",
-                            svc_name, team_id, err2
-                        );
-                        Ok(())
-                    })
-            })
-            .map(|_| ())
+                            svc_name2, team_id, err
+                        ),
+                    }
+                    Ok(())
+                })
         }))
         .map(|_| ())
     }
 }
+
+/// A check_up or noise action against one team, as part of a check round.
+struct Run {
+    service_mux: Arc<Mutex<Service>>,
+    limiter: Option<Limiter>,
+    db: Db,
+    schedule: Schedule,
+    action: Action,
+    team: TeamConfig,
+    /// The check number.
+    tick: i32,
+    log_dir: PathBuf,
+    shutdown: Shutdown,
+}
+
+impl Run {
+    /// Waits for the run's turn in the schedule and then runs the action, retrying it according
+    /// to the service's policy. Resolves to the public message of the run that counts.
+    fn check(self) -> impl Future<Item = Option<String>, Error = GameServerError> + Send + Sync {
+        let Run {
+            service_mux,
+            limiter,
+            db,
+            schedule,
+            action,
+            team,
+            tick,
+            log_dir,
+            shutdown,
+        } = self;
+        let team_id = team.id;
+        let (delay, policy, service_limiter) = {
+            let service = service_mux.lock().unwrap();
+            let window = schedule.window(&service, limiter.as_ref());
+            let delay = schedule.offset(window, team_id, &service.name, action.name());
+            let policy = service.config.retry.policy(action);
+            (delay, policy, service.limiter.clone())
+        };
+
+        let turn = wait_turn(delay, &shutdown);
+
+        // each attempt waits for its own slots, so retries don't hold them during backoff
+        let run = retry(
+            policy,
+            GameServerError::is_retryable,
+            move |attempt, deadline| {
+                let service_mux = service_mux.clone();
+                let (team, log_dir, db) = (team.clone(), log_dir.clone(), db.clone());
+                let slots =
+                    acquire_slots(limiter.clone(), service_limiter.clone(), shutdown.clone());
+                slots.and_then(move |slots| {
+                    let service = service_mux.lock().unwrap();
+                    info!(
+                        "{} service={} team_id={} attempt={} queued={:?}",
+                        action.name(),
+                        service.name,
+                        team_id,
+                        attempt,
+                        queued(&slots)
+                    );
+                    let ctx = service.context(action, &team, tick, log_dir);
+                    let ctx = attempt_context(ctx, attempt, deadline);
+                    let record = attempt_recorder(db, &ctx, attempt);
+                    let run = match action {
+                        Action::Noise(_) => {
+                            Either::A(service.noise(ctx).map_err(GameServerError::Noise))
+                        }
+                        _ => Either::B(service.check_up(ctx).map_err(GameServerError::CheckUp)),
+                    };
+                    run.then(move |result| {
+                        drop(slots);
+                        match &result {
+                            Ok(message) => record(CheckerStatus::Ok, message.clone()),
+                            Err(err) => record(err.status(), err.public_message()),
+                        }
+                        result
+                    })
+                })
+            },
+        );
+
+        turn.and_then(|_| run)
+    }
+}
+
+/// The config for a team that was added to the running game.
+fn added_team(team: models::Team) -> Option<TeamConfig> {
+    let ip = match team.ip.parse() {
+        Ok(ip) => ip,
+        Err(err) => {
+            error!("not playing team {}: {}", team.id, err);
+            return None;
+        }
+    };
+    Some(TeamConfig {
+        id: team.id,
+        ip,
+        name: team.name,
+        affiliation: team.affiliation,
+        country: team.country,
+        logo: team.logo,
+        nop: team.nop,
+        services: HashMap::new(),
+    })
+}
+
+/// Warns about a run against a NOP team that didn't come back `Ok`. The NOP team runs the
+/// services as they were shipped, so this points at a broken checker or service.
+fn warn_if_nop(nop: bool, team_id: i32, action: Action, service: &str, status: CheckerStatus) {
+    if nop && status != CheckerStatus::Ok {
+        warn!(
+            "{} service={} failed against NOP team {} with {:?}, the checker or the service may be broken",
+            action.name(),
+            service,
+            team_id,
+            status
+        );
+    }
+}
+
+/// Returns a function that records the verdict of the `attempt`th attempt at the run described
+/// by `ctx`, once it's known. Failing to record it only gets logged.
+fn attempt_recorder(
+    db: Db,
+    ctx: &CheckContext,
+    attempt: u32,
+) -> impl FnOnce(CheckerStatus, Option<String>) {
+    let started = Instant::now();
+    let (action, tick, team_id) = (ctx.action, ctx.tick, ctx.team_id);
+    let service_name = ctx.service.clone();
+    move |status, message| {
+        if status != CheckerStatus::Ok {
+            info!(
+                "{} service={} team_id={} tick={} attempt={} => {:?}",
+                action.as_str(),
+                service_name,
+                team_id,
+                tick,
+                attempt,
+                status
+            );
+        }
+        let new_attempt = NewAttempt {
+            action: action.as_str().to_owned(),
+            tick,
+            team_id,
+            service_name,
+            attempt: attempt as i32,
+            status,
+            message,
+            duration_ms: started.elapsed().as_millis() as i32,
+        };
+        if let Err(err) = db.insert_attempt(&new_attempt) {
+            warn!("failed to record attempt: {}", err);
+        }
+    }
+}
+
+pub(crate) type LoadedServices = Vec<(String, Result<Service, GameServerError>)>;
+
+/// Loads every service in the services directory, registering each one in the db. Services that
+/// fail to load are returned along with their error rather than failing the whole load.
+fn load_services(
+    config: &Config,
+    checkers: &Registry,
+    db: &Db,
+) -> Result<LoadedServices, GameServerError> {
+    let services = read_services(config, checkers)?
+        .into_iter()
+        .map(|(name, service)| {
+            let service = service.and_then(|service| {
+                db.add_service(&models::Service {
+                    name: name.clone(),
+                    port: service.config.port.into(),
+                    atk_score: service.config.atk_score as i32,
+                    def_score: service.config.def_score as i32,
+                    up_score: service.config.up_score as i32,
+                })
+                .map(|_| service)
+                .map_err(GameServerError::Db)
+            });
+            (name, service)
+        })
+        .collect();
+    Ok(services)
+}
+
+/// Loads every service in the services directory that isn't ignored, without touching the db.
+pub(crate) fn read_services(
+    config: &Config,
+    checkers: &Registry,
+) -> Result<LoadedServices, GameServerError> {
+    // list the directory
+    let read_dir = fs::read_dir(&config.services_dir).map_err(GameServerError::ListServices)?;
+    let mut services = Vec::new();
+    for entry in read_dir {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                services.push((String::new(), Err(GameServerError::ReadEntry(err))));
+                continue;
+            }
+        };
+
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(name) => {
+                services.push((String::new(), Err(GameServerError::OsString(name))));
+                continue;
+            }
+        };
+
+        // ignore non-directories
+        if !entry
+            .file_type()
+            .map_err(GameServerError::ReadEntry)?
+            .is_dir()
+        {
+            continue;
+        }
+
+        // ignore directories starting with .
+        if name.starts_with('.') {
+            continue;
+        }
+
+        // ignore files listed explicitly in ignores
+        if config.ignores.contains(&name) {
+            continue;
+        }
+
+        let path = entry.path();
+        let service = Service::load_from_dir(config, checkers, &name, &path)
+            .map_err(GameServerError::Service);
+        services.push((name, service));
+    }
+    Ok(services)
+}
+
+/// Waits for a checker slot from the service's own limit and then from the global one. Taking
+/// the service slot first keeps a saturated service from hogging global slots while it waits.
+///
+/// Fails with [`GameServerError::ShutDown`] instead once a shutdown is requested, so no new
+/// checkers are started while the running ones finish.
+fn acquire_slots(
+    global: Option<Limiter>,
+    service: Option<Limiter>,
+    shutdown: Shutdown,
+) -> impl Future<Item = (Slot, Slot), Error = GameServerError> + Send + Sync {
+    fn acquire(
+        limiter: Option<Limiter>,
+    ) -> Box<dyn Future<Item = Slot, Error = GameServerError> + Send + Sync> {
+        match limiter {
+            Some(limiter) => Box::new(limiter.acquire().map_err(GameServerError::Limit)),
+            None => Box::new(future::ok(Slot::unlimited())),
+        }
+    }
+
+    let slots = acquire(service).and_then(move |service_slot| {
+        acquire(global).map(move |global_slot| (service_slot, global_slot))
+    });
+    let interrupted = shutdown.wait().then(|_| Err(GameServerError::ShutDown));
+    slots
+        .select(interrupted)
+        .map(|(slots, _)| slots)
+        .map_err(|(err, _)| err)
+        .and_then(move |slots| {
+            if shutdown.is_requested() {
+                Err(GameServerError::ShutDown)
+            } else {
+                Ok(slots)
+            }
+        })
+}
+
+/// Waits until `delay` has passed, or less if a shutdown is requested in the meantime.
+fn wait_turn(
+    delay: Duration,
+    shutdown: &Shutdown,
+) -> impl Future<Item = (), Error = GameServerError> + Send + Sync {
+    Delay::new(Instant::now() + delay)
+        .map_err(GameServerError::Delay)
+        .select(shutdown.wait().then(|_| Ok(())))
+        .map(|_| ())
+        .map_err(|(err, _)| err)
+}
+
+/// Total time spent waiting for a pair of slots from [`acquire_slots`].
+fn queued((service_slot, global_slot): &(Slot, Slot)) -> Duration {
+    service_slot.waited() + global_slot.waited()
+}
@@ -0,0 +1,134 @@
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv6Addr, ToSocketAddrs};
+use std::str::FromStr;
+
+use futures::future::{self, Either};
+use futures::Future;
+use serde::de::{self, Deserialize, Deserializer};
+
+use crate::service::ServiceError;
+
+/// Longest hostname DNS allows.
+const MAX_HOSTNAME: usize = 253;
+
+/// Where a team's vulnbox is: an IPv4 or IPv6 address, or a hostname. Checkers are given it as
+/// it's written in the config, and hostnames are looked up again for every run, so a vulnbox
+/// may move during the game. Hosts that are neither are rejected when the config is loaded.
+///
+/// # Examples
+///
+/// ```
+/// # use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+/// # use gameserver::host::Host;
+/// let v4: Host = "10.0.0.1".parse().unwrap();
+/// assert_eq!(v4.ip(), Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))));
+/// let v6: Host = "[fd00::1]".parse().unwrap();
+/// assert_eq!(v6.ip(), Some(IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1))));
+/// let name: Host = "team1.ctf.local".parse().unwrap();
+/// assert_eq!(name.ip(), None);
+/// assert_eq!(name.to_string(), "team1.ctf.local");
+/// assert!("team 1".parse::<Host>().is_err());
+/// assert!("10.0.0.999".parse::<Host>().is_err());
+/// assert!("[fd00::1".parse::<Host>().is_err());
+/// assert!(serde_json::from_str::<Host>(r#""team 1""#).is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(transparent)]
+pub struct Host(String);
+
+impl Host {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The address, if the host is written as one. IPv6 addresses may be in brackets.
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self.0.strip_prefix('[') {
+            Some(bracketed) => {
+                let ip = bracketed.strip_suffix(']')?.parse::<Ipv6Addr>().ok()?;
+                Some(IpAddr::V6(ip))
+            }
+            None => self.0.parse().ok(),
+        }
+    }
+
+    /// Whether the host is an address or could be a hostname. A hostname's last label can't be
+    /// all digits, so a mistyped address like `10.0.0.999` isn't taken for one.
+    pub fn is_valid(&self) -> bool {
+        if self.ip().is_some() {
+            return true;
+        }
+        let name = self.0.strip_suffix('.').unwrap_or(&self.0);
+        let numeric = |label: &str| label.chars().all(|c| c.is_ascii_digit());
+        !name.is_empty()
+            && name.len() <= MAX_HOSTNAME
+            && !name.rsplit('.').next().is_some_and(numeric)
+            && name.split('.').all(|label| {
+                (1..=63).contains(&label.len())
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+                    && label
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            })
+    }
+
+    /// Looks the host up, blocking until it's done. Addresses are returned as they are.
+    pub fn lookup(&self) -> io::Result<IpAddr> {
+        if let Some(ip) = self.ip() {
+            return Ok(ip);
+        }
+        (self.0.as_str(), 0)
+            .to_socket_addrs()?
+            .next()
+            .map(|addr| addr.ip())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no addresses"))
+    }
+
+    /// Looks the host up without holding up the other futures on the runtime, which has to be
+    /// a thread pool.
+    pub fn resolve(&self) -> impl Future<Item = IpAddr, Error = ServiceError> + Send + Sync {
+        if let Some(ip) = self.ip() {
+            return Either::A(future::ok(ip));
+        }
+        let host = self.clone();
+        let lookup = future::poll_fn(move || tokio_threadpool::blocking(|| host.lookup()));
+        let host = self.clone();
+        Either::B(lookup.then(move |result| match result {
+            Ok(Ok(ip)) => Ok(ip),
+            Ok(Err(err)) => Err(ServiceError::Resolve(host, err)),
+            Err(err) => Err(ServiceError::Resolve(
+                host,
+                io::Error::other(err.to_string()),
+            )),
+        }))
+    }
+}
+
+impl FromStr for Host {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let host = Host(s.to_owned());
+        if host.is_valid() {
+            Ok(host)
+        } else {
+            Err(format!("{:?} isn't an IP address or hostname", s))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Host {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
@@ -1,3 +1,4 @@
+use hmac::{Hmac, Mac};
 use sha2::{Digest, Sha256};
 
 pub fn generate_flag(tick: i32, team_id: i32, service_name: impl AsRef<str>) -> String {
@@ -9,3 +10,27 @@ pub fn generate_flag(tick: i32, team_id: i32, service_name: impl AsRef<str>) ->
     let result = hasher.result();
     format!("flag{{{}|hmac={:x}}}", payload, result)
 }
+
+/// A seed for a checker's random choices. It only depends on `key` and what the run is for, so
+/// running a checker again for the same flag makes the same choices, but it can't be worked out
+/// without the key.
+pub fn generate_seed(
+    key: &[u8],
+    purpose: &str,
+    tick: i32,
+    team_id: i32,
+    service_name: impl AsRef<str>,
+) -> u64 {
+    let service_name = service_name.as_ref();
+    let payload = format!(
+        "purpose={}|tick={}|team={}|svc={}",
+        purpose, tick, team_id, service_name
+    );
+
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("HMAC takes keys of any length");
+    mac.input(payload.as_bytes());
+    let result = mac.result().code();
+    let mut seed = [0; 8];
+    seed.copy_from_slice(&result[..8]);
+    u64::from_be_bytes(seed)
+}
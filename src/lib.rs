@@ -11,19 +11,32 @@ extern crate log;
 #[macro_use]
 extern crate serde_derive;
 
+pub mod builtin;
 pub mod check_up;
+pub mod checker;
 pub mod flag_io;
+pub mod host;
+pub mod lifecycle;
 
 mod config;
 pub mod db;
 mod game;
 mod key;
+pub mod limit;
 pub mod models;
+pub mod oneshot;
+pub mod reload;
+pub mod remote;
+pub mod retry;
+pub mod schedule;
 pub mod schema;
 pub mod service;
+pub mod shutdown;
+pub mod status;
 pub mod util;
+pub mod validate;
 pub mod web;
 
-pub use crate::config::{Config, TeamConfig};
+pub use crate::config::{parse_country, Config, ConfigError, ServiceAddress, TeamConfig};
 pub use crate::db::{Db, DbError};
 pub use crate::game::GameServer;
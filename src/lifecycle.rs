@@ -0,0 +1,207 @@
+use std::error::Error as StdError;
+use std::io::Write;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+
+use crate::config::TeamConfig;
+use crate::db::{Db, DbError};
+use crate::host::Host;
+use crate::models::NewAuditEntry;
+
+/// Where a team is in the game. Only active teams are checked and get new flags.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, AsExpression, FromSqlRow,
+)]
+#[serde(rename_all = "lowercase")]
+#[sql_type = "Text"]
+pub enum TeamState {
+    Active,
+    /// Not checked and not given new flags until it's resumed. Its old flags can still be
+    /// claimed.
+    Suspended,
+    /// Out of the game for good: not checked, not ranked, and its submissions are rejected.
+    Disqualified,
+}
+
+impl TeamState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TeamState::Active => "active",
+            TeamState::Suspended => "suspended",
+            TeamState::Disqualified => "disqualified",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for TeamState {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for TeamState {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"active" => Ok(TeamState::Active),
+            b"suspended" => Ok(TeamState::Suspended),
+            b"disqualified" => Ok(TeamState::Disqualified),
+            state => Err(format!("unknown team state {:?}", String::from_utf8_lossy(state)).into()),
+        }
+    }
+}
+
+/// A change to the teams of a running game, made with `gameserver team` or
+/// `POST /admin/teams`. The gameserver picks it up at the start of its next round.
+///
+/// # Examples
+///
+/// ```
+/// # use gameserver::lifecycle::TeamChange;
+/// let change: TeamChange = serde_json::from_str(r#"
+///     {"action": "disqualify", "team_id": 3, "void_captures": true}
+/// "#).unwrap();
+/// assert_eq!(change.team_id(), 3);
+/// let change: TeamChange = serde_json::from_str(r#"
+///     {"action": "add", "id": 9, "ip": "10.0.0.9", "name": "Latecomers"}
+/// "#).unwrap();
+/// assert_eq!((change.as_str(), change.team_id()), ("add", 9));
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum TeamChange {
+    /// Adds a team that isn't in the config, which is played from the next tick on. Its
+    /// services are played at its `ip`, so `services` overrides are rejected.
+    Add(TeamConfig),
+    Suspend {
+        team_id: i32,
+    },
+    Resume {
+        team_id: i32,
+    },
+    /// Disqualifies a team. Its captures are taken back if `void_captures` is set, and kept
+    /// otherwise.
+    Disqualify {
+        team_id: i32,
+        #[serde(default)]
+        void_captures: bool,
+    },
+}
+
+impl TeamChange {
+    pub fn team_id(&self) -> i32 {
+        match self {
+            TeamChange::Add(team) => team.id,
+            TeamChange::Suspend { team_id }
+            | TeamChange::Resume { team_id }
+            | TeamChange::Disqualify { team_id, .. } => *team_id,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TeamChange::Add(_) => "add",
+            TeamChange::Suspend { .. } => "suspend",
+            TeamChange::Resume { .. } => "resume",
+            TeamChange::Disqualify { .. } => "disqualify",
+        }
+    }
+}
+
+#[derive(Debug, Display)]
+pub enum LifecycleError {
+    Db(DbError),
+    #[display(fmt = "team {} already exists", _0)]
+    TeamExists(i32),
+    #[display(fmt = "there's no team {}", _0)]
+    NoSuchTeam(i32),
+    #[display(fmt = "{:?} isn't an IP address or hostname", _0)]
+    InvalidHost(Host),
+    /// Per-service addresses can only be set in the config.
+    #[display(
+        fmt = "team {} sets addresses for its services, add it to the config instead",
+        _0
+    )]
+    ServiceOverrides(i32),
+    /// Disqualification is final.
+    #[display(fmt = "team {} is disqualified", _0)]
+    Disqualified(i32),
+}
+
+impl StdError for LifecycleError {}
+
+impl From<DbError> for LifecycleError {
+    fn from(err: DbError) -> Self {
+        LifecycleError::Db(err)
+    }
+}
+
+/// Makes `change` and records it in the audit log as done by `actor`, like `cli (alice)`.
+/// Returns a description of what was done. The change and its audit entry are made in one
+/// transaction, so either both are made or neither is.
+pub fn apply(db: &Db, change: &TeamChange, actor: &str) -> Result<String, LifecycleError> {
+    let team_id = change.team_id();
+    let message = db.transaction(|tx| {
+        let existing = tx.get_team(team_id).map_err(LifecycleError::Db)?;
+        let (message, detail) = match (change, existing) {
+            (TeamChange::Add(_), Some(_)) => return Err(LifecycleError::TeamExists(team_id)),
+            (TeamChange::Add(team), None) => {
+                if !team.ip.is_valid() {
+                    return Err(LifecycleError::InvalidHost(team.ip.clone()));
+                }
+                if !team.services.is_empty() {
+                    return Err(LifecycleError::ServiceOverrides(team_id));
+                }
+                tx.insert_team(team).map_err(LifecycleError::Db)?;
+                let message = format!("team {} added, it's played from the next round", team_id);
+                (message, Some(format!("ip={}", team.ip)))
+            }
+            (_, None) => return Err(LifecycleError::NoSuchTeam(team_id)),
+            (_, Some(team)) if team.state == TeamState::Disqualified => {
+                return Err(LifecycleError::Disqualified(team_id));
+            }
+            (TeamChange::Suspend { .. }, Some(_)) => {
+                tx.set_team_state(team_id, TeamState::Suspended)
+                    .map_err(LifecycleError::Db)?;
+                let message = format!("team {} suspended from the next round", team_id);
+                (message, None)
+            }
+            (TeamChange::Resume { .. }, Some(_)) => {
+                tx.set_team_state(team_id, TeamState::Active)
+                    .map_err(LifecycleError::Db)?;
+                let message = format!("team {} resumed from the next round", team_id);
+                (message, None)
+            }
+            (TeamChange::Disqualify { void_captures, .. }, Some(_)) => {
+                tx.set_team_state(team_id, TeamState::Disqualified)
+                    .map_err(LifecycleError::Db)?;
+                let voided = if *void_captures {
+                    tx.void_captures(team_id).map_err(LifecycleError::Db)?
+                } else {
+                    0
+                };
+                let message = if *void_captures {
+                    format!("team {} disqualified, {} captures voided", team_id, voided)
+                } else {
+                    format!("team {} disqualified, its captures are kept", team_id)
+                };
+                let detail = format!("void_captures={} voided={}", void_captures, voided);
+                (message, Some(detail))
+            }
+        };
+
+        let entry = NewAuditEntry {
+            actor: actor.to_owned(),
+            action: change.as_str().to_owned(),
+            team_id,
+            detail,
+        };
+        tx.insert_audit_entry(&entry).map_err(LifecycleError::Db)?;
+        Ok(message)
+    })?;
+    info!("{}: {}", actor, message);
+    Ok(message)
+}
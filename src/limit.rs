@@ -0,0 +1,121 @@
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::prelude::*;
+use tokio_sync::semaphore::{AcquireError, Permit, Semaphore};
+
+/// Caps the number of checker subprocesses that may run at the same time.
+///
+/// Cloning a `Limiter` shares the same pool of slots.
+///
+/// # Examples
+///
+/// ```
+/// # use futures::Future;
+/// # use std::num::NonZeroUsize;
+/// # use gameserver::limit::Limiter;
+/// let limiter = Limiter::new(NonZeroUsize::new(1).unwrap());
+/// let slot = limiter.acquire().wait().unwrap();
+/// assert_eq!(limiter.available(), 0);
+/// drop(slot);
+/// assert_eq!(limiter.available(), 1);
+/// ```
+#[derive(Clone)]
+pub struct Limiter {
+    semaphore: Arc<Semaphore>,
+    slots: usize,
+}
+
+impl Limiter {
+    pub fn new(slots: NonZeroUsize) -> Self {
+        Limiter {
+            semaphore: Arc::new(Semaphore::new(slots.get())),
+            slots: slots.get(),
+        }
+    }
+
+    /// Returns the total number of slots.
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    /// Returns the number of slots that are currently free.
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+
+    /// Waits for a free slot. The slot is given back when the returned [`Slot`] is dropped.
+    pub fn acquire(&self) -> Acquire {
+        Acquire {
+            semaphore: Some(self.semaphore.clone()),
+            permit: Permit::new(),
+            started: Instant::now(),
+        }
+    }
+}
+
+/// Future returned by [`Limiter::acquire`].
+pub struct Acquire {
+    semaphore: Option<Arc<Semaphore>>,
+    permit: Permit,
+    started: Instant,
+}
+
+impl Future for Acquire {
+    type Item = Slot;
+    type Error = AcquireError;
+
+    fn poll(&mut self) -> Result<Async<Slot>, AcquireError> {
+        let semaphore = self
+            .semaphore
+            .take()
+            .expect("Acquire polled after completion");
+        match self.permit.poll_acquire(&semaphore)? {
+            Async::Ready(()) => {
+                let permit = std::mem::replace(&mut self.permit, Permit::new());
+                Ok(Async::Ready(Slot {
+                    semaphore: Some(semaphore),
+                    permit,
+                    waited: self.started.elapsed(),
+                }))
+            }
+            Async::NotReady => {
+                self.semaphore = Some(semaphore);
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
+
+/// A held checker slot, released on drop.
+///
+/// A slot that didn't come from a [`Limiter`] (see [`Slot::unlimited`]) holds nothing.
+pub struct Slot {
+    semaphore: Option<Arc<Semaphore>>,
+    permit: Permit,
+    waited: Duration,
+}
+
+impl Slot {
+    pub fn unlimited() -> Self {
+        Slot {
+            semaphore: None,
+            permit: Permit::new(),
+            waited: Duration::from_secs(0),
+        }
+    }
+
+    /// How long this slot spent queued before it was acquired.
+    pub fn waited(&self) -> Duration {
+        self.waited
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        if let Some(semaphore) = &self.semaphore {
+            self.permit.release(semaphore);
+        }
+    }
+}
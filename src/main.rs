@@ -1,13 +1,25 @@
-use std::fs::File;
-use std::io::Read;
+#[macro_use]
+extern crate log;
+
+use std::env;
 use std::path::PathBuf;
+use std::process;
 use std::sync::{Arc, Mutex};
-use std::thread;
+use std::time::{Duration, Instant};
 
+use gameserver::checker::Registry;
+use gameserver::host::Host;
+use gameserver::lifecycle::{self, TeamChange};
+use gameserver::oneshot::{Actions, OneShot};
+use gameserver::reload;
+use gameserver::shutdown::{self, Shutdown};
+use gameserver::validate::validate;
 use gameserver::{check_up, flag_io};
-use gameserver::{Config, Db, GameServer};
+use gameserver::{parse_country, Config, Db, GameServer, TeamConfig};
 use structopt::StructOpt;
 use tokio::prelude::*;
+use tokio::runtime::Runtime;
+use tokio::timer::Delay;
 
 #[derive(StructOpt)]
 struct Opt {
@@ -25,39 +37,256 @@ enum Command {
 
     #[structopt(name = "migrate")]
     Migrate,
+
+    /// Checks the config, the services and the db schema, and reports any problems.
+    #[structopt(name = "validate")]
+    Validate,
+
+    /// Runs a service's checkers once against a target, without touching the db.
+    #[structopt(name = "check")]
+    Check {
+        #[structopt(long = "service")]
+        service: String,
+
+        /// The address or hostname to check, instead of the team's address from the config.
+        #[structopt(long = "target")]
+        target: Option<Host>,
+
+        /// put, get, check, put_noise, get_noise, havoc, or all for a check_up and the service's
+        /// noise actions followed by a put and a get of the same flag.
+        #[structopt(long = "action", default_value = "all")]
+        action: Actions,
+
+        /// The team the flag is generated for, and whose address is checked.
+        #[structopt(long = "team", default_value = "1")]
+        team_id: i32,
+
+        /// The tick the flag is generated for.
+        #[structopt(long = "tick", default_value = "0")]
+        tick: i32,
+
+        /// The flag id from an earlier put, for a get on its own.
+        #[structopt(long = "flag-id")]
+        flag_id: Option<String>,
+    },
+
+    /// Changes the teams of a game, which a running gameserver picks up at the start of its
+    /// next round.
+    #[structopt(name = "team")]
+    Team {
+        #[structopt(subcommand)]
+        change: TeamCommand,
+    },
+}
+
+#[derive(StructOpt)]
+enum TeamCommand {
+    /// Adds a team that isn't in the config.
+    #[structopt(name = "add")]
+    Add {
+        #[structopt(long = "team")]
+        team_id: i32,
+        #[structopt(long = "ip")]
+        ip: Host,
+        #[structopt(long = "name")]
+        name: Option<String>,
+        #[structopt(long = "affiliation")]
+        affiliation: Option<String>,
+        #[structopt(long = "country", parse(try_from_str = "parse_country"))]
+        country: Option<String>,
+        #[structopt(long = "logo")]
+        logo: Option<String>,
+        /// Adds an unranked reference team.
+        #[structopt(long = "nop")]
+        nop: bool,
+    },
+
+    /// Stops checking a team and planting flags on its vulnbox until it's resumed.
+    #[structopt(name = "suspend")]
+    Suspend {
+        #[structopt(long = "team")]
+        team_id: i32,
+    },
+
+    #[structopt(name = "resume")]
+    Resume {
+        #[structopt(long = "team")]
+        team_id: i32,
+    },
+
+    /// Takes a team out of the game for good and rejects its submissions.
+    #[structopt(name = "disqualify")]
+    Disqualify {
+        #[structopt(long = "team")]
+        team_id: i32,
+        /// Takes back the flags the team claimed, instead of keeping them.
+        #[structopt(long = "void-captures")]
+        void_captures: bool,
+    },
+}
+
+impl TeamCommand {
+    fn change(&self) -> TeamChange {
+        match self {
+            TeamCommand::Add {
+                team_id,
+                ip,
+                name,
+                affiliation,
+                country,
+                logo,
+                nop,
+            } => TeamChange::Add(TeamConfig {
+                id: *team_id,
+                ip: ip.clone(),
+                name: name.clone(),
+                affiliation: affiliation.clone(),
+                country: country.clone(),
+                logo: logo.clone(),
+                nop: *nop,
+                services: Default::default(),
+            }),
+            TeamCommand::Suspend { team_id } => TeamChange::Suspend { team_id: *team_id },
+            TeamCommand::Resume { team_id } => TeamChange::Resume { team_id: *team_id },
+            TeamCommand::Disqualify {
+                team_id,
+                void_captures,
+            } => TeamChange::Disqualify {
+                team_id: *team_id,
+                void_captures: *void_captures,
+            },
+        }
+    }
 }
 
 fn main() {
     env_logger::builder().default_format_timestamp(false).init();
     let opt = Opt::from_args();
 
+    // this has to cope with a config that doesn't load
+    if let Command::Validate = opt.cmd {
+        let report = validate(&opt.config, &Registry::new());
+        println!("{}", report);
+        process::exit(if report.has_errors() { 1 } else { 0 });
+    }
+
     // read the config file
-    let mut file = File::open(opt.config).expect("config file couldn't be opened");
-    let mut contents = Vec::new();
-    file.read_to_end(&mut contents)
-        .expect("failed to read config");
-    let config: Config = toml::from_slice(contents.as_slice()).expect("couldn't parse config");
+    let config = Config::load(&opt.config).expect("couldn't load config");
 
-    // connect to the db
-    let db = Db::connect(&config.db).expect("couldn't connect to the db");
+    let connect = || Db::connect(&config.db).expect("couldn't connect to the db");
 
     match &opt.cmd {
         Command::Run => {
+            let db = connect();
             let bind_addr = config.bind_addr;
+            let shutdown_timeout = Duration::from_secs(config.shutdown_timeout.into());
 
-            let gameserver = GameServer::new(config.clone()).expect("couldn't load gameserver");
+            let gameserver =
+                GameServer::new(config.clone(), &opt.config).expect("couldn't load gameserver");
+            let reload = gameserver.get_reload();
+            let shared_config = gameserver.get_shared_config();
             let gameserver = Arc::new(Mutex::new(gameserver));
+            let shutdown = Shutdown::new();
+
+            let run = future::lazy(move || {
+                let check_up = check_up::ticker(gameserver.clone(), shutdown.clone());
+                let flag_io = flag_io::ticker(gameserver.clone(), shutdown.clone());
 
-            let check_up = check_up::ticker(gameserver.clone());
-            let flag_io = flag_io::ticker(gameserver.clone());
+                // once a shutdown is requested, the checkers that are still running get some
+                // time to finish, unless it's forced. after that they're dropped, which kills
+                // their subprocesses, and their jobs are picked back up the next time the
+                // gameserver starts.
+                let deadline = shutdown
+                    .wait()
+                    .and_then(move |_| {
+                        Delay::new(Instant::now() + shutdown_timeout).map_err(|err| {
+                            error!("Timer error: {}", err);
+                        })
+                    })
+                    .map(|_| warn!("shutdown timed out, killing the remaining checkers"))
+                    .select(shutdown.wait_forced())
+                    .map(|_| ())
+                    .map_err(|_| ());
+                let tickers = check_up
+                    .join(flag_io)
+                    .map(|_| ())
+                    .select(deadline)
+                    .map(|_| ())
+                    .map_err(|_| ());
 
-            thread::spawn(move || {
-                gameserver::web::run(config, bind_addr, db);
+                let web = gameserver::web::serve(
+                    shared_config,
+                    bind_addr,
+                    db,
+                    reload.clone(),
+                    shutdown.wait(),
+                );
+                let reloads = reload::listen(reload)
+                    .select(shutdown.wait())
+                    .map(|_| ())
+                    .map_err(|_| ());
+                // the signal handling task doesn't finish by itself, so don't wait for it
+                tokio::spawn(shutdown::listen(shutdown));
+                tickers.join3(web, reloads)
             });
-            tokio::run(check_up.join(flag_io).map(|_| ()));
+
+            let mut runtime = Runtime::new().expect("couldn't start the runtime");
+            let _ = runtime.block_on(run);
+            let _ = runtime.shutdown_now().wait();
+            info!("shutdown complete");
         }
+        Command::Validate => unreachable!("handled before the config is loaded"),
         Command::Migrate => {
-            db.migrate().expect("failed to migrate");
+            connect().migrate().expect("failed to migrate");
+        }
+        Command::Team { change } => {
+            let actor = format!(
+                "cli ({})",
+                env::var("USER").unwrap_or_else(|_| "unknown".to_owned())
+            );
+            match lifecycle::apply(&connect(), &change.change(), &actor) {
+                Ok(message) => println!("{}", message),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                }
+            }
+        }
+        Command::Check {
+            service,
+            target,
+            action,
+            team_id,
+            tick,
+            flag_id,
+        } => {
+            let configured = config.teams.iter().find(|team| team.id == *team_id);
+            let team = match (target, configured) {
+                (Some(ip), _) => TeamConfig {
+                    id: *team_id,
+                    ip: ip.clone(),
+                    name: None,
+                    affiliation: None,
+                    country: None,
+                    logo: None,
+                    nop: false,
+                    services: Default::default(),
+                },
+                (None, Some(team)) => team.clone(),
+                (None, None) => {
+                    eprintln!(
+                        "there's no team {} in the config, so pass --target",
+                        team_id
+                    );
+                    process::exit(1);
+                }
+            };
+            let mut oneshot = OneShot::new(&config, &Registry::new(), service, team, *tick)
+                .expect("couldn't load the service");
+            let log_dir = config.log_directory.join("oneshot");
+            if !oneshot.run(*action, log_dir, flag_id.clone()) {
+                process::exit(1);
+            }
         }
     }
 }
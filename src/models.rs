@@ -1,6 +1,8 @@
 use chrono::NaiveDateTime;
 
-use crate::schema::{check_ups, flags, services, teams};
+use crate::lifecycle::TeamState;
+use crate::schema::{attempts, audit_log, check_ups, flags, services, teams};
+use crate::status::CheckerStatus;
 
 #[derive(Queryable)]
 pub struct Tick {
@@ -14,17 +16,45 @@ pub struct Tick {
 pub struct Team {
     pub id: i32,
     pub arbitrary_bonus_points: i32,
-    pub ip: i32,
+    /// An IP address or hostname, as it's written in the config.
+    pub ip: String,
+    pub name: Option<String>,
+    pub affiliation: Option<String>,
+    pub country: Option<String>,
+    pub logo: Option<String>,
+    /// Whether this is a reference team that's checked and attacked, but not ranked.
+    pub nop: bool,
+    pub state: TeamState,
+    /// Whether the team was added to the running game rather than in the config.
+    pub added_by_admin: bool,
 }
 
-#[derive(Insertable)]
+impl Team {
+    /// The team's name, or `Team <id>` if it doesn't have one.
+    pub fn display_name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!("Team {}", self.id),
+        }
+    }
+}
+
+/// A team as it's described in the config. Details the config leaves out are cleared.
+#[derive(Insertable, AsChangeset)]
 #[table_name = "teams"]
+#[changeset_options(treat_none_as_null = "true")]
 pub struct NewTeam {
     pub id: i32,
-    pub ip: i32,
+    pub ip: String,
+    pub name: Option<String>,
+    pub affiliation: Option<String>,
+    pub country: Option<String>,
+    pub logo: Option<String>,
+    pub nop: bool,
+    pub added_by_admin: bool,
 }
 
-#[derive(Debug, Queryable, Insertable)]
+#[derive(Debug, Queryable, Insertable, AsChangeset)]
 pub struct Service {
     pub name: String,
     pub port: i32,
@@ -45,8 +75,98 @@ pub struct Flag {
 
     pub in_progress: bool,
     pub claimed_by: Option<i32>,
-    pub defended: bool,
     pub created: NaiveDateTime,
+    pub lost: bool,
+    /// The result of the latest get_flag, or `None` if it hasn't been checked yet. For flags that
+    /// weren't planted, it's the result of set_flag.
+    pub status: Option<CheckerStatus>,
+    /// The public message from the latest checker run on this flag.
+    pub message: Option<String>,
+    /// Whether set_flag succeeded. Flags that weren't planted can't be claimed or defended.
+    pub planted: bool,
+}
+
+impl Flag {
+    /// Whether the flag's owner gets defense points for it, which takes a get_flag that
+    /// retrieved it.
+    pub fn defended(&self) -> bool {
+        self.planted
+            && self.claimed_by.is_none()
+            && !self.lost
+            && self.status == Some(CheckerStatus::Ok)
+    }
+
+    /// The flag after a get_flag that came back with `status` and `message`. A flag that was
+    /// retrieved keeps its defense when the service is down or mumbling later, or the checker
+    /// breaks, but one that was retrieved and then comes back corrupt is lost for good.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use chrono::NaiveDateTime;
+    /// # use gameserver::models::Flag;
+    /// # use gameserver::status::CheckerStatus::*;
+    /// let flag = Flag {
+    ///     tick: 3,
+    ///     team_id: 1,
+    ///     service_name: "notes".to_owned(),
+    ///     flag: "flag{...}".to_owned(),
+    ///     flag_id: None,
+    ///     in_progress: false,
+    ///     claimed_by: None,
+    ///     created: NaiveDateTime::from_timestamp(0, 0),
+    ///     lost: false,
+    ///     status: None,
+    ///     message: None,
+    ///     planted: true,
+    /// };
+    /// // nothing is earned before the flag has been retrieved
+    /// assert!(!flag.defended());
+    /// assert!(!flag.checked(Error, None).defended());
+    /// assert!(!flag.checked(Down, None).defended());
+    ///
+    /// let retrieved = flag.checked(Ok, None);
+    /// assert!(retrieved.defended());
+    /// for status in [Down, Mumble, Error] {
+    ///     let later = retrieved.checked(status, Some("later".to_owned()));
+    ///     assert_eq!((later.status, later.message), (Some(Ok), None));
+    /// }
+    ///
+    /// let corrupt = retrieved.checked(Corrupt, None);
+    /// assert!(corrupt.lost && !corrupt.defended());
+    /// // a lost flag stays lost even if it shows up again
+    /// assert!(!corrupt.checked(Ok, None).defended());
+    /// // one that was never retrieved isn't lost, and can still be defended
+    /// let missing = flag.checked(Corrupt, None);
+    /// assert!(!missing.lost);
+    /// assert!(missing.checked(Ok, None).defended());
+    /// ```
+    pub fn checked(&self, status: CheckerStatus, message: Option<String>) -> Flag {
+        let retrieved = self.status == Some(CheckerStatus::Ok);
+        let mut flag = self.clone();
+        match status {
+            // a broken checker doesn't tell us anything about the flag
+            CheckerStatus::Error if self.status.is_some() => return flag,
+            // the flag was defended when it was retrieved, and a service that's down or
+            // mumbling now doesn't take that back
+            CheckerStatus::Down | CheckerStatus::Mumble if retrieved => return flag,
+            // a flag that was there before and has now disappeared is lost for good
+            CheckerStatus::Corrupt => flag.lost = self.lost || retrieved,
+            _ => (),
+        }
+        flag.status = Some(status);
+        flag.message = message;
+        flag
+    }
+
+    /// Whether set_flag failed in a way that's the team's fault, which costs them uptime.
+    pub fn missed(&self) -> bool {
+        !self.planted
+            && self
+                .status
+                .map(CheckerStatus::counts_against)
+                .unwrap_or(true)
+    }
 }
 
 #[derive(Insertable)]
@@ -58,6 +178,10 @@ pub struct NewFlag {
 
     pub flag: String,
     pub flag_id: Option<String>,
+    pub message: Option<String>,
+    pub planted: bool,
+    /// set_flag's verdict, for flags that weren't planted.
+    pub status: Option<CheckerStatus>,
 }
 
 #[derive(Clone, Queryable, Insertable, Serialize)]
@@ -66,6 +190,37 @@ pub struct CheckUp {
     pub team_id: i32,
     pub service_name: String,
     pub in_progress: bool,
-    pub up: bool,
     pub timestamp: NaiveDateTime,
+    pub status: CheckerStatus,
+    pub message: Option<String>,
+}
+
+/// One run of a checker. Retried runs get one of these per attempt.
+#[derive(Insertable)]
+#[table_name = "attempts"]
+pub struct NewAttempt {
+    /// `check`, `put`, `get` or a noise action.
+    pub action: String,
+    /// The tick the flag belongs to, or the check number for check_ups and noise.
+    pub tick: i32,
+    pub team_id: i32,
+    pub service_name: String,
+
+    /// Which attempt this was, from 1.
+    pub attempt: i32,
+    pub status: CheckerStatus,
+    pub message: Option<String>,
+    pub duration_ms: i32,
+}
+
+/// A change an admin made to a team. See [`crate::lifecycle`].
+#[derive(Insertable)]
+#[table_name = "audit_log"]
+pub struct NewAuditEntry {
+    /// Who made the change, like `cli (alice)` or `web (10.0.0.2:51234)`.
+    pub actor: String,
+    /// `add`, `suspend`, `resume` or `disqualify`.
+    pub action: String,
+    pub team_id: i32,
+    pub detail: Option<String>,
 }
@@ -0,0 +1,273 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use tokio::runtime::Runtime;
+
+use crate::checker::{Action, Noise, Registry};
+use crate::config::{Config, TeamConfig};
+use crate::key::generate_flag;
+use crate::service::{Service, ServiceError};
+use crate::status::CheckerStatus;
+
+/// Which of a service's checkers `gameserver check` runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Actions {
+    Check,
+    Put,
+    Get,
+    Noise(Noise),
+    /// check_up and the service's noise actions, then a set_flag and a get_flag of the same
+    /// flag.
+    All,
+}
+
+impl FromStr for Actions {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "check" => Ok(Actions::Check),
+            "put" => Ok(Actions::Put),
+            "get" => Ok(Actions::Get),
+            "put_noise" => Ok(Actions::Noise(Noise::PutNoise)),
+            "get_noise" => Ok(Actions::Noise(Noise::GetNoise)),
+            "havoc" => Ok(Actions::Noise(Noise::Havoc)),
+            "all" => Ok(Actions::All),
+            _ => Err(format!(
+                "unknown action {:?}, expected put, get, check, put_noise, get_noise, havoc or all",
+                s
+            )),
+        }
+    }
+}
+
+/// A one-off run of a service's checkers against a single team, outside of the game. Nothing
+/// is read from or written to the database, and failed runs aren't retried.
+pub struct OneShot {
+    service: Service,
+    team: TeamConfig,
+    tick: i32,
+    runtime: Runtime,
+}
+
+/// What happened in one run.
+struct Report {
+    action: Action,
+    status: CheckerStatus,
+    duration: Duration,
+    detail: Option<String>,
+    message: Option<String>,
+    error: Option<ServiceError>,
+}
+
+impl OneShot {
+    /// Loads `service` from the services directory. The runs are made against `team` in `tick`,
+    /// which decide the flag that's planted.
+    pub fn new(
+        config: &Config,
+        checkers: &Registry,
+        service: &str,
+        team: TeamConfig,
+        tick: i32,
+    ) -> Result<Self, ServiceError> {
+        let path = config.services_dir.join(service);
+        let service = Service::load_from_dir(config, checkers, service, path)?;
+        let runtime = Runtime::new().map_err(ServiceError::Spawn)?;
+        Ok(OneShot {
+            service,
+            team,
+            tick,
+            runtime,
+        })
+    }
+
+    /// Runs `actions`, printing each verdict, how long it took and the checker's output, which
+    /// is also kept under `log_dir`. For `get` on its own, `flag_id` is what an earlier `put`
+    /// returned. Returns whether every run was OK.
+    pub fn run(
+        &mut self,
+        actions: Actions,
+        log_dir: impl AsRef<Path>,
+        flag_id: Option<String>,
+    ) -> bool {
+        let log_dir = log_dir.as_ref().join(&self.service.name);
+        // so output left over from an earlier run isn't printed
+        let _ = fs::remove_dir_all(&log_dir);
+        let flag = generate_flag(self.tick, self.team.id, &self.service.name);
+        let mut reports = Vec::new();
+
+        if let Actions::Check | Actions::All = actions {
+            reports.push(self.check(&log_dir, Action::CheckUp));
+        }
+        let noise = match actions {
+            Actions::Noise(noise) => vec![noise],
+            Actions::All => self.service.config.noise.clone(),
+            _ => Vec::new(),
+        };
+        for noise in noise {
+            reports.push(self.check(&log_dir, Action::Noise(noise)));
+        }
+        // services without flags only have check_up to run
+        if actions == Actions::All && !self.service.has_flags() {
+            println!("no flags in this service, skipping set_flag and get_flag");
+            return reports
+                .iter()
+                .all(|report| report.status == CheckerStatus::Ok);
+        }
+        let mut flag_id = flag_id;
+        if let Actions::Put | Actions::All = actions {
+            let report = self.set_flag(&log_dir, flag.clone());
+            if report.status == CheckerStatus::Ok {
+                flag_id = report.detail.clone();
+            }
+            let failed = report.status != CheckerStatus::Ok;
+            reports.push(report);
+            // there's nothing to get if the flag wasn't planted
+            if failed && actions == Actions::All {
+                return false;
+            }
+        }
+        if let Actions::Get | Actions::All = actions {
+            reports.push(self.get_flag(&log_dir, &flag, flag_id));
+        }
+
+        reports
+            .iter()
+            .all(|report| report.status == CheckerStatus::Ok)
+    }
+
+    /// Runs check_up or a noise action.
+    fn check(&mut self, log_dir: &Path, action: Action) -> Report {
+        let log_dir = log_dir.join(action.name());
+        let ctx = self
+            .service
+            .context(action, &self.team, self.tick, &log_dir);
+        let started = Instant::now();
+        let result = match action {
+            Action::Noise(_) => self.runtime.block_on(self.service.noise(ctx)),
+            _ => self.runtime.block_on(self.service.check_up(ctx)),
+        };
+        let report = match result {
+            Ok(message) => Report::ok(action, started, None, message),
+            Err(err) => Report::failed(action, started, err),
+        };
+        report.print(&log_dir);
+        report
+    }
+
+    fn set_flag(&mut self, log_dir: &Path, flag: String) -> Report {
+        let log_dir = log_dir.join("set_flag");
+        let ctx = self
+            .service
+            .context(Action::SetFlag, &self.team, self.tick, &log_dir);
+        println!("flag: {}", flag);
+        let started = Instant::now();
+        let result = self.runtime.block_on(self.service.set_flag(ctx, flag));
+        let report = match result {
+            Ok((flag_id, message)) => Report::ok(Action::SetFlag, started, flag_id, message),
+            Err(err) => Report::failed(Action::SetFlag, started, err),
+        };
+        report.print(&log_dir);
+        report
+    }
+
+    fn get_flag(&mut self, log_dir: &Path, flag: &str, flag_id: Option<String>) -> Report {
+        let log_dir = log_dir.join("get_flag");
+        let ctx = self
+            .service
+            .context(Action::GetFlag, &self.team, self.tick, &log_dir);
+        let started = Instant::now();
+        let result = self.runtime.block_on(self.service.get_flag(ctx, flag_id));
+        let report = match result {
+            Ok((ref found, ref message)) if found == flag => Report::ok(
+                Action::GetFlag,
+                started,
+                Some(found.clone()),
+                message.clone(),
+            ),
+            Ok((found, message)) => Report {
+                status: CheckerStatus::Corrupt,
+                ..Report::ok(Action::GetFlag, started, Some(found), message)
+            },
+            Err(err) => Report::failed(Action::GetFlag, started, err),
+        };
+        report.print(&log_dir);
+        report
+    }
+}
+
+impl Report {
+    fn ok(
+        action: Action,
+        started: Instant,
+        detail: Option<String>,
+        message: Option<String>,
+    ) -> Self {
+        Report {
+            action,
+            status: CheckerStatus::Ok,
+            duration: started.elapsed(),
+            detail,
+            message,
+            error: None,
+        }
+    }
+
+    fn failed(action: Action, started: Instant, err: ServiceError) -> Self {
+        Report {
+            action,
+            status: err.status(),
+            duration: started.elapsed(),
+            detail: None,
+            message: err.public_message(),
+            error: Some(err),
+        }
+    }
+
+    /// Prints the report, along with the output the checker left in `log_dir`.
+    fn print(&self, log_dir: &Path) {
+        println!("{}", self);
+        for name in &["stdout.log", "stderr.log"] {
+            if let Ok(output) = fs::read(log_dir.join(name)) {
+                if !output.is_empty() {
+                    println!("  --- {}", name);
+                    for line in String::from_utf8_lossy(&output).lines() {
+                        println!("  | {}", line);
+                    }
+                }
+            }
+        }
+        println!("  logs: {}", log_dir.display());
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:<9} {:<7} {:.3}s",
+            self.action.as_str(),
+            self.status.as_str().to_uppercase(),
+            self.duration.as_secs_f64()
+        )?;
+        if let Some(detail) = &self.detail {
+            let what = match self.action {
+                Action::SetFlag => "flag_id",
+                _ => "flag",
+            };
+            write!(f, "\n  {}: {}", what, detail)?;
+        }
+        if let Some(message) = &self.message {
+            write!(f, "\n  message: {}", message)?;
+        }
+        match &self.error {
+            // the verdict and message already say it all
+            None | Some(ServiceError::Verdict(..)) => (),
+            Some(err) => write!(f, "\n  error: {:?}", err)?,
+        }
+        Ok(())
+    }
+}
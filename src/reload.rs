@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use tokio::prelude::*;
+use tokio_signal::unix::{Signal, SIGHUP};
+
+/// Schedules a reload of the config file and the services directory.
+///
+/// Requesting a reload only sets a flag; the gameserver applies it at the start of the next
+/// tick (see [`GameServer::reload_if_requested`](crate::GameServer::reload_if_requested)).
+///
+/// # Examples
+///
+/// ```
+/// # use gameserver::reload::Reload;
+/// let reload = Reload::new();
+/// reload.clone().request();
+/// assert!(reload.take());
+/// assert!(!reload.take());
+/// ```
+#[derive(Clone, Default)]
+pub struct Reload(Arc<AtomicBool>);
+
+impl Reload {
+    pub fn new() -> Self {
+        Reload::default()
+    }
+
+    pub fn request(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether a reload was requested, clearing the request.
+    pub fn take(&self) -> bool {
+        self.0.swap(false, Ordering::SeqCst)
+    }
+}
+
+/// Requests a reload every time the process receives SIGHUP.
+///
+/// The returned future only finishes if the signal handler can't be installed, in which case
+/// the error is logged.
+pub fn listen(reload: Reload) -> impl Future<Item = (), Error = ()> {
+    Signal::new(SIGHUP)
+        .flatten_stream()
+        .for_each(move |_| {
+            info!("received SIGHUP, reloading at the start of the next tick");
+            reload.request();
+            Ok(())
+        })
+        .map_err(|err| error!("couldn't listen for SIGHUP: {}", err))
+}
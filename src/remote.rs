@@ -0,0 +1,202 @@
+use std::str::FromStr;
+
+use futures::{future, Future, Stream};
+use hyper::{header, Body, Client, Request, Uri};
+
+use crate::checker::{CheckContext, CheckFuture, Checker};
+use crate::service::ServiceError;
+use crate::status::CheckerStatus;
+use crate::util::Locked;
+
+/// A checker running as a long-lived HTTP service.
+///
+/// Every run is a `POST` to the checker's URL with a JSON [`RemoteRequest`], which is answered
+/// with a JSON [`RemoteVerdict`]. If the checker can't be reached or its answer can't be
+/// understood, the run counts as a checker `Error`.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::PathBuf;
+/// # use std::time::Duration;
+/// # use futures::Future;
+/// # use warp::Filter;
+/// # use gameserver::checker::{Action, CheckContext, Checker};
+/// # use gameserver::remote::{RemoteChecker, RemoteRequest, RemoteVerdict};
+/// # use gameserver::status::CheckerStatus;
+/// // a stub checker that finds every service it's asked about broken
+/// let stub = warp::post2()
+///     .and(warp::body::json())
+///     .map(|req: RemoteRequest| {
+///         assert_eq!(req.action, "check");
+///         warp::reply::json(&RemoteVerdict {
+///             status: CheckerStatus::Mumble,
+///             message: Some(format!("{} is broken", req.ip)),
+///             ..Default::default()
+///         })
+///     });
+/// let (addr, server) = warp::serve(stub).bind_ephemeral(([127, 0, 0, 1], 0));
+///
+/// let checker: RemoteChecker = format!("http://{}/", addr).parse().unwrap();
+/// let ctx = CheckContext {
+///     action: Action::CheckUp,
+///     service: "echo".to_owned(),
+///     team_id: 1,
+///     tick: 0,
+///     target: "team1.ctf".parse().unwrap(),
+///     addr: None,
+///     port: 9999,
+///     timeout: Duration::from_secs(5),
+///     log_dir: PathBuf::from("logs"),
+/// };
+/// let mut runtime = tokio::runtime::Runtime::new().unwrap();
+/// runtime.spawn(server);
+/// let err = runtime.block_on(checker.check_up(ctx)).unwrap_err();
+/// assert_eq!(err.status(), CheckerStatus::Mumble);
+/// assert_eq!(err.public_message().unwrap(), "team1.ctf is broken");
+/// ```
+#[derive(Clone, Debug)]
+pub struct RemoteChecker {
+    url: Uri,
+}
+
+/// What a [`RemoteChecker`] is asked to do.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RemoteRequest {
+    /// `put` to plant a flag, `get` to retrieve one, `check` to check that the service works, or
+    /// `put_noise`, `get_noise` or `havoc` for the noise actions.
+    pub action: String,
+    pub service: String,
+    pub team_id: i32,
+    /// The team's address or hostname, as it's written in the config.
+    pub ip: String,
+    pub port: u16,
+    pub tick: i32,
+    /// How many seconds the checker has to answer.
+    pub timeout: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flag_id: Option<String>,
+}
+
+/// A [`RemoteChecker`]'s answer.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RemoteVerdict {
+    pub status: CheckerStatus,
+    /// For `put`, the flag id to pass back with `get`.
+    #[serde(default)]
+    pub flag_id: Option<String>,
+    /// For `get`, the flag that was found.
+    #[serde(default)]
+    pub flag: Option<String>,
+    /// A public message for the team.
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+impl Default for RemoteVerdict {
+    fn default() -> Self {
+        RemoteVerdict {
+            status: CheckerStatus::Ok,
+            flag_id: None,
+            flag: None,
+            message: None,
+        }
+    }
+}
+
+impl FromStr for RemoteChecker {
+    type Err = ServiceError;
+
+    fn from_str(url: &str) -> Result<Self, ServiceError> {
+        if url.starts_with("https://") {
+            return Err(ServiceError::HttpsChecker(url.to_owned()));
+        }
+        let url = url.parse().map_err(ServiceError::Uri)?;
+        Ok(RemoteChecker { url })
+    }
+}
+
+impl RemoteChecker {
+    fn call(
+        &self,
+        ctx: CheckContext,
+        flag: Option<String>,
+        flag_id: Option<String>,
+    ) -> CheckFuture<RemoteVerdict> {
+        let request = RemoteRequest {
+            action: ctx.action.as_str().to_owned(),
+            service: ctx.service,
+            team_id: ctx.team_id,
+            ip: ctx.target.to_string(),
+            port: ctx.port,
+            tick: ctx.tick,
+            timeout: ctx.timeout.as_secs(),
+            flag,
+            flag_id,
+        };
+        let body = match serde_json::to_vec(&request) {
+            Ok(body) => body,
+            Err(err) => return Box::new(future::err(ServiceError::Json(err))),
+        };
+        let request = Request::post(self.url.clone())
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .expect("the request is always valid");
+
+        let verdict = Client::new()
+            .request(request)
+            .map_err(ServiceError::Remote)
+            .and_then(|response| {
+                let status = response.status();
+                response
+                    .into_body()
+                    .concat2()
+                    .map_err(ServiceError::Remote)
+                    .and_then(move |body| {
+                        if !status.is_success() {
+                            return Err(ServiceError::RemoteStatus(status));
+                        }
+                        serde_json::from_slice::<RemoteVerdict>(&body).map_err(ServiceError::Json)
+                    })
+            })
+            .and_then(|verdict| match verdict.status {
+                CheckerStatus::Ok => Ok(verdict),
+                status => Err(ServiceError::Verdict(status, verdict.message)),
+            });
+        Box::new(Locked::new(verdict))
+    }
+}
+
+impl Checker for RemoteChecker {
+    fn check_up(&self, ctx: CheckContext) -> CheckFuture<Option<String>> {
+        Box::new(self.call(ctx, None, None).map(|verdict| verdict.message))
+    }
+
+    fn set_flag(
+        &self,
+        ctx: CheckContext,
+        flag: String,
+    ) -> CheckFuture<(Option<String>, Option<String>)> {
+        Box::new(
+            self.call(ctx, Some(flag), None)
+                .map(|verdict| (verdict.flag_id, verdict.message)),
+        )
+    }
+
+    fn get_flag(
+        &self,
+        ctx: CheckContext,
+        flag_id: Option<String>,
+    ) -> CheckFuture<(String, Option<String>)> {
+        Box::new(
+            self.call(ctx, None, flag_id)
+                .map(|verdict| (verdict.flag.unwrap_or_default(), verdict.message)),
+        )
+    }
+
+    fn noise(&self, ctx: CheckContext) -> CheckFuture<Option<String>> {
+        Box::new(self.call(ctx, None, None).map(|verdict| verdict.message))
+    }
+}
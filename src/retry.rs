@@ -0,0 +1,154 @@
+use std::time::{Duration, Instant};
+
+use futures::future::{self, Either, Loop};
+use futures::Future;
+use tokio::timer::Delay;
+
+use crate::checker::{Action, CheckContext};
+
+/// How a checker run that fails is retried. Unset fields fall back to the ones in the `[retry]`
+/// table, then to not retrying at all.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// How many times the checker may run, counting the first run.
+    #[serde(default)]
+    pub attempts: Option<u32>,
+    /// Seconds to wait before the first retry. The wait doubles after every retry.
+    #[serde(default)]
+    pub backoff: Option<u32>,
+    /// Seconds all the attempts together may take. Attempts are cut short so they don't run past
+    /// it, and no retry is started if its backoff would.
+    #[serde(default)]
+    pub deadline: Option<u32>,
+}
+
+impl RetryPolicy {
+    /// Fills in the fields that aren't set from `fallback`.
+    pub fn or(self, fallback: RetryPolicy) -> RetryPolicy {
+        RetryPolicy {
+            attempts: self.attempts.or(fallback.attempts),
+            backoff: self.backoff.or(fallback.backoff),
+            deadline: self.deadline.or(fallback.deadline),
+        }
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts.unwrap_or(1).max(1)
+    }
+
+    /// How long to wait after the `attempt`th attempt fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use gameserver::retry::RetryPolicy;
+    /// let policy = RetryPolicy { backoff: Some(2), ..Default::default() };
+    /// assert_eq!(policy.backoff(1), Duration::from_secs(2));
+    /// assert_eq!(policy.backoff(3), Duration::from_secs(8));
+    /// ```
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = u64::from(self.backoff.unwrap_or(1));
+        Duration::from_secs(backoff << attempt.saturating_sub(1).min(16))
+    }
+
+    pub fn deadline(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| Duration::from_secs(deadline.into()))
+    }
+}
+
+/// A service's `[retry]` table: a policy for every action, which the `[retry.check_up]`,
+/// `[retry.set_flag]` and `[retry.get_flag]` tables can override.
+///
+/// # Examples
+///
+/// ```
+/// # use gameserver::checker::Action;
+/// # use gameserver::retry::RetryConfig;
+/// let config: RetryConfig = toml::from_str(r#"
+///     attempts = 3
+///     [get_flag]
+///     attempts = 5
+///     deadline = 20
+/// "#).unwrap();
+/// assert_eq!(config.policy(Action::CheckUp).attempts(), 3);
+/// assert_eq!(config.policy(Action::GetFlag).attempts(), 5);
+/// assert_eq!(config.policy(Action::GetFlag).deadline.unwrap(), 20);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RetryConfig {
+    #[serde(flatten)]
+    pub all: RetryPolicy,
+    #[serde(default)]
+    pub check_up: RetryPolicy,
+    #[serde(default)]
+    pub set_flag: RetryPolicy,
+    #[serde(default)]
+    pub get_flag: RetryPolicy,
+}
+
+impl RetryConfig {
+    pub fn policy(&self, action: Action) -> RetryPolicy {
+        let policy = match action {
+            // noise counts like check_up, so it gets the same chances
+            Action::CheckUp | Action::Noise(_) => self.check_up,
+            Action::SetFlag => self.set_flag,
+            Action::GetFlag => self.get_flag,
+        };
+        policy.or(self.all)
+    }
+}
+
+/// Adjusts `ctx` for the `attempt`th attempt at a run that has to be done by `deadline`: the
+/// timeout is cut to fit, and retries log to their own `attempt_NN` directory.
+pub fn attempt_context(
+    mut ctx: CheckContext,
+    attempt: u32,
+    deadline: Option<Instant>,
+) -> CheckContext {
+    if let Some(deadline) = deadline {
+        let left = deadline.saturating_duration_since(Instant::now());
+        ctx.timeout = ctx.timeout.min(left);
+    }
+    if attempt > 1 {
+        ctx.log_dir = ctx.log_dir.join(format!("attempt_{:02}", attempt));
+    }
+    ctx
+}
+
+/// Runs `attempt` until its future succeeds or `policy` gives up, resolving to the last
+/// attempt's result. `attempt` is given the attempt number, from 1, and the deadline, if there
+/// is one. An error that isn't `retryable` ends the retries right away.
+pub fn retry<T, E, F, Fut>(
+    policy: RetryPolicy,
+    retryable: fn(&E) -> bool,
+    mut attempt: F,
+) -> impl Future<Item = T, Error = E> + Send + Sync
+where
+    F: FnMut(u32, Option<Instant>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Item = T, Error = E> + Send + Sync + 'static,
+    T: Send + Sync + 'static,
+    E: Send + Sync + 'static,
+{
+    // loop_fn starts the first attempt right away, so wait to be polled before starting the clock
+    future::lazy(move || {
+        let deadline = policy.deadline().map(|deadline| Instant::now() + deadline);
+        future::loop_fn(1, move |n| {
+            attempt(n, deadline).then(move |result| match result {
+                Ok(value) => Either::A(future::ok(Loop::Break(Ok(value)))),
+                Err(err) => {
+                    let retry_at = Instant::now() + policy.backoff(n);
+                    let out_of_time = deadline.is_some_and(|deadline| retry_at >= deadline);
+                    if n >= policy.attempts() || out_of_time || !retryable(&err) {
+                        Either::A(future::ok(Loop::Break(Err(err))))
+                    } else {
+                        // a broken timer only means retrying early
+                        Either::B(Delay::new(retry_at).then(move |_| Ok(Loop::Continue(n + 1))))
+                    }
+                }
+            })
+        })
+    })
+    .and_then(future::result)
+}
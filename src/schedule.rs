@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{RngCore, SeedableRng};
+
+use crate::key::generate_seed;
+use crate::limit::Limiter;
+use crate::service::Service;
+
+/// When the jobs of a round start, and in which order the teams queue for checker slots.
+/// Everything is derived from a random seed that's logged at the start of the round, so a round's schedule
+/// can be worked out again from the logs.
+#[derive(Clone, Copy, Debug)]
+pub struct Schedule {
+    seed: u64,
+    round_length: Duration,
+    spread: Option<f64>,
+    teams: usize,
+    services: usize,
+}
+
+impl Schedule {
+    /// A schedule with a new seed for a round lasting `round_length`, spreading jobs over the
+    /// `spread` fraction of it. `teams` and `services` are how many of each the round plays.
+    pub fn new(round_length: Duration, spread: Option<f64>, teams: usize, services: usize) -> Self {
+        let seed = rand::thread_rng().next_u64();
+        Schedule::with_seed(seed, round_length, spread, teams, services)
+    }
+
+    /// The schedule that was used in a round that logged `seed`.
+    pub fn with_seed(
+        seed: u64,
+        round_length: Duration,
+        spread: Option<f64>,
+        teams: usize,
+        services: usize,
+    ) -> Self {
+        Schedule {
+            seed,
+            round_length,
+            spread,
+            teams,
+            services,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Puts the teams in the round's order. Jobs that are due at the same time wait for checker
+    /// slots in this order.
+    pub fn shuffle<T>(&self, teams: &mut [T]) {
+        teams.shuffle(&mut StdRng::seed_from_u64(self.seed));
+    }
+
+    /// How long into the round `service`'s jobs may start: the service's own `delay` if it sets
+    /// one, then the configured fraction of the round, then the global `delay`.
+    ///
+    /// If the round has more jobs than `limiter` or the service's own limiter has slots, the
+    /// window is widened to the rest of the round less the service's timeout, so the jobs that
+    /// would have queued start throughout the round rather than all at its beginning.
+    pub fn window(&self, service: &Service, limiter: Option<&Limiter>) -> Duration {
+        let window = match (service.config.delay, self.spread) {
+            (None, Some(spread)) => self.round_length.mul_f64(spread.clamp(0.0, 1.0)),
+            _ => Duration::from_secs(service.delay.into()),
+        };
+        let crowded = limiter.is_some_and(|limiter| self.teams * self.services > limiter.slots())
+            || (service.limiter.as_ref()).is_some_and(|limiter| self.teams > limiter.slots());
+        if crowded {
+            let timeout = Duration::from_secs(service.timeout.into());
+            window.max(self.round_length.saturating_sub(timeout))
+        } else {
+            window
+        }
+    }
+
+    /// When `job` against `team_id`'s `service` starts, as a random point in `window` that only
+    /// depends on the seed and the job.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use gameserver::schedule::Schedule;
+    /// let schedule = Schedule::with_seed(42, Duration::from_secs(60), Some(0.5), 10, 3);
+    /// let window = Duration::from_secs(30);
+    /// let offset = schedule.offset(window, 3, "notes", "set_flag");
+    /// assert!(offset < window);
+    /// assert_eq!(offset, schedule.offset(window, 3, "notes", "set_flag"));
+    /// assert_eq!(schedule.offset(Duration::from_secs(0), 3, "notes", "set_flag"), Duration::from_secs(0));
+    /// ```
+    pub fn offset(&self, window: Duration, team_id: i32, service: &str, job: &str) -> Duration {
+        let window = window.as_millis() as u64;
+        if window == 0 {
+            return Duration::from_secs(0);
+        }
+        let purpose = format!("job={}", job);
+        let random = generate_seed(&self.seed.to_be_bytes(), &purpose, 0, team_id, service);
+        Duration::from_millis(random % window)
+    }
+}
+
+/// The services that still need running when a round is resumed, leaving out the ones in `done`
+/// that finished before the restart. They keep the order of `services`.
+///
+/// # Examples
+///
+/// ```
+/// # use gameserver::schedule::pending;
+/// let services = ["notes", "bank", "chat"];
+/// let name = |service: &&str| service.to_string();
+///
+/// // nothing ran yet
+/// assert_eq!(pending(&services, name, &[]), ["notes", "bank", "chat"]);
+/// // the round was cut short after bank finished
+/// assert_eq!(pending(&services, name, &["bank".to_owned()]), ["notes", "chat"]);
+/// // everything finished, and a service that was since removed doesn't matter
+/// let done = ["chat", "notes", "bank", "old"].iter().map(|s| s.to_string()).collect::<Vec<_>>();
+/// assert!(pending(&services, name, &done).is_empty());
+/// ```
+pub fn pending<T: Clone>(services: &[T], name: impl Fn(&T) -> String, done: &[String]) -> Vec<T> {
+    services
+        .iter()
+        .filter(|service| !done.contains(&name(service)))
+        .cloned()
+        .collect()
+}
@@ -1,11 +1,38 @@
+table! {
+    attempts (id) {
+        id -> Int4,
+        action -> Varchar,
+        tick -> Int4,
+        team_id -> Int4,
+        service_name -> Varchar,
+        attempt -> Int4,
+        status -> Varchar,
+        message -> Nullable<Text>,
+        duration_ms -> Int4,
+        timestamp -> Timestamp,
+    }
+}
+
+table! {
+    audit_log (id) {
+        id -> Int4,
+        timestamp -> Timestamp,
+        actor -> Text,
+        action -> Varchar,
+        team_id -> Int4,
+        detail -> Nullable<Text>,
+    }
+}
+
 table! {
     check_ups (id, team_id, service_name) {
         id -> Int4,
         team_id -> Int4,
         service_name -> Varchar,
         in_progress -> Bool,
-        up -> Bool,
         timestamp -> Timestamp,
+        status -> Varchar,
+        message -> Nullable<Text>,
     }
 }
 
@@ -18,8 +45,11 @@ table! {
         flag_id -> Nullable<Text>,
         in_progress -> Bool,
         claimed_by -> Nullable<Int4>,
-        defended -> Bool,
         created -> Timestamp,
+        lost -> Bool,
+        status -> Nullable<Varchar>,
+        message -> Nullable<Text>,
+        planted -> Bool,
     }
 }
 
@@ -37,7 +67,14 @@ table! {
     teams (id) {
         id -> Int4,
         arbitrary_bonus_points -> Int4,
-        ip -> Int4,
+        ip -> Text,
+        name -> Nullable<Text>,
+        affiliation -> Nullable<Text>,
+        country -> Nullable<Varchar>,
+        logo -> Nullable<Text>,
+        nop -> Bool,
+        state -> Varchar,
+        added_by_admin -> Bool,
     }
 }
 
@@ -50,9 +87,12 @@ table! {
     }
 }
 
+joinable!(attempts -> services (service_name));
+joinable!(attempts -> teams (team_id));
+joinable!(audit_log -> teams (team_id));
 joinable!(check_ups -> services (service_name));
 joinable!(check_ups -> teams (team_id));
 joinable!(flags -> services (service_name));
 joinable!(flags -> teams (team_id));
 
-allow_tables_to_appear_in_same_query!(check_ups, flags, services, teams, tick,);
+allow_tables_to_appear_in_same_query!(attempts, audit_log, check_ups, flags, services, teams, tick,);
@@ -1,37 +1,166 @@
+use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::{self, Read};
-use std::net::Ipv4Addr;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::process::{Command, Output};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use futures::future::Either;
 use tokio::prelude::*;
+use tokio::timer::Timeout;
 
-use crate::config::Config;
-use crate::util::{TimeoutCommand, TimeoutCommandError};
+use crate::builtin::{GenericCheck, GenericChecker};
+use crate::checker::{Action, CheckContext, CheckFuture, Checker, Noise, Registry};
+use crate::config::{Config, TeamConfig};
+use crate::host::Host;
+use crate::key::generate_seed;
+use crate::limit::Limiter;
+use crate::remote::RemoteChecker;
+use crate::retry::RetryConfig;
+use crate::status::CheckerStatus;
+use crate::util::{ResourceLimits, TimeoutCommand, TimeoutCommandError};
 
 pub struct Service {
     pub name: String,
     pub config: ServiceConfig,
 
     pub timeout: u32,
+    pub delay: u32,
+    pub log_max_bytes: Option<usize>,
+    pub limits: ResourceLimits,
+    /// Keys the seeds checkers get, so teams can't work them out.
+    pub secret_key: String,
     pub base_dir: PathBuf,
+    pub limiter: Option<Limiter>,
 
-    pub get_flag_path: PathBuf,
-    pub set_flag_path: PathBuf,
-    pub check_up_path: PathBuf,
+    pub backend: Backend,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// What actually runs a service's checks.
+pub enum Backend {
+    /// The service's get_flag, set_flag and check_up executables.
+    Executables {
+        get_flag_path: PathBuf,
+        set_flag_path: PathBuf,
+        check_up_path: PathBuf,
+        /// The executables for the noise actions in the service's `noise` list.
+        noise_paths: HashMap<Noise, PathBuf>,
+    },
+    /// The `command` from `meta.toml`, run for every action.
+    Command(Vec<String>),
+    /// A checker from the [`Registry`] running inside the gameserver, or a remote one.
+    Native(Arc<dyn Checker>),
+    /// A check_up declared in `meta.toml`. These services don't have flags.
+    Generic(GenericChecker),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct ServiceConfig {
-    pub port: u32,
+    pub port: u16,
 
     pub atk_score: u32,
     pub def_score: u32,
     pub up_score: u32,
 
+    /// Maximum number of this service's checkers that may run at once.
+    #[serde(default)]
+    pub max_checkers: Option<NonZeroUsize>,
+
+    /// Seconds each run may take, overriding the global `timeout`.
+    #[serde(default)]
+    pub timeout: Option<u32>,
+
+    /// Per-action timeouts, overriding `timeout`.
+    #[serde(default)]
+    pub timeouts: ActionTimeouts,
+
+    /// Width of the window runs are randomly spread over, overriding the global `delay`.
+    #[serde(default)]
+    pub delay: Option<u32>,
+
+    /// A program to run the executables with, such as `python3`.
+    #[serde(default)]
+    pub interpreter: Option<String>,
+
+    /// How failed runs are retried.
+    #[serde(default)]
+    pub retry: RetryConfig,
+
+    /// A command to run for every action instead of the three executables, such as
+    /// `["python3", "checker.py", "{action}", "{ip}", "{port}"]`. See [`expand_template`] for
+    /// the placeholders.
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+
+    /// A checker to use instead of executables: either one from the registry, as
+    /// `builtin:<name>`, or the URL of a remote checker.
+    #[serde(default)]
+    pub checker: Option<String>,
+
+    /// Resource limits for this service's executables, overriding the global ones.
+    #[serde(default)]
+    pub limits: ResourceLimits,
+
+    /// How the executables get the details of each run.
+    #[serde(default)]
+    pub invocation: Invocation,
+
+    /// A generic check_up to run instead of executables, for services without flags.
+    #[serde(default)]
+    pub check: Option<GenericCheck>,
+
+    /// Noise actions to run in every check round along with check_up.
+    #[serde(default)]
+    pub noise: Vec<Noise>,
+
     pub get_flag_path: Option<PathBuf>,
     pub set_flag_path: Option<PathBuf>,
     pub check_up_path: Option<PathBuf>,
+    pub put_noise_path: Option<PathBuf>,
+    pub get_noise_path: Option<PathBuf>,
+    pub havoc_path: Option<PathBuf>,
+}
+
+/// Timeouts for single actions, in seconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ActionTimeouts {
+    #[serde(default)]
+    pub check_up: Option<u32>,
+    #[serde(default)]
+    pub set_flag: Option<u32>,
+    #[serde(default)]
+    pub get_flag: Option<u32>,
+    /// For every noise action.
+    #[serde(default)]
+    pub noise: Option<u32>,
+}
+
+impl ActionTimeouts {
+    pub fn get(&self, action: Action) -> Option<u32> {
+        match action {
+            Action::CheckUp => self.check_up,
+            Action::SetFlag => self.set_flag,
+            Action::GetFlag => self.get_flag,
+            Action::Noise(_) => self.noise,
+        }
+    }
+}
+
+/// How a service's executables are called.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Invocation {
+    /// `<ip> <port> [flag | flag_id]` on the command line, or whatever the `command` template
+    /// says.
+    #[default]
+    Args,
+    /// `<ip> <port>` (or the `command` template) on the command line, the flag on stdin, and
+    /// everything else in `SERVICE`, `TEAM_ID`, `TICK`, `TIMEOUT`, `FLAG_ID` and `SEED`
+    /// environment variables. `SEED` is the same for the set_flag and get_flag of a flag. The
+    /// template can't use `{flag}` or `{flag_id}`, which would put them on the command line.
+    Env,
 }
 
 #[derive(Debug)]
@@ -44,6 +173,27 @@ pub enum ServiceError {
     DecodeOutput(std::string::FromUtf8Error),
     Spawn(io::Error),
     Subprocess(TimeoutCommandError),
+    UnknownChecker(String),
+    /// Remote checkers are only reached over plain HTTP.
+    HttpsChecker(String),
+    Regex(regex::Error),
+    /// The generic http check's path doesn't start with `/`.
+    RelativePath(String),
+    Uri(hyper::http::uri::InvalidUri),
+    Status(hyper::http::status::InvalidStatusCode),
+    NoFlags,
+    NoNoise,
+    EmptyCommand,
+    /// The `command` template uses `{flag}` or `{flag_id}`, but the service is invoked with
+    /// `env`.
+    FlagInCommand,
+    Json(serde_json::Error),
+    Remote(hyper::Error),
+    RemoteStatus(hyper::StatusCode),
+    TimedOut,
+    Timer(tokio::timer::Error),
+    /// The team's host couldn't be looked up.
+    Resolve(Host, io::Error),
 
     GetFlagIO(io::Error),
     SetFlagIO(io::Error),
@@ -52,6 +202,73 @@ pub enum ServiceError {
     GetFlagError,
     SetFlagError,
     CheckUpError,
+
+    /// The checker ran, but reported something other than `Ok`, along with its public message.
+    Verdict(CheckerStatus, Option<String>),
+}
+
+impl ServiceError {
+    /// The verdict to record for a checker run that failed with this error.
+    pub fn status(&self) -> CheckerStatus {
+        match self {
+            ServiceError::Verdict(status, _) => *status,
+            ServiceError::Subprocess(TimeoutCommandError::TimedOut)
+            | ServiceError::TimedOut
+            | ServiceError::Resolve(..) => CheckerStatus::Down,
+            // the service's responses made the checker write too much
+            ServiceError::Subprocess(TimeoutCommandError::OutputLimit) => CheckerStatus::Mumble,
+            _ => CheckerStatus::Error,
+        }
+    }
+
+    /// The public message the checker printed before failing, if any, or what the team needs
+    /// to know about a failure that came before the checker ran.
+    pub fn public_message(&self) -> Option<String> {
+        match self {
+            ServiceError::Verdict(_, message) => message.clone(),
+            ServiceError::Resolve(host, err) => Some(format!("can't resolve {}: {}", host, err)),
+            _ => None,
+        }
+    }
+}
+
+/// Longest public message kept from a checker, in characters.
+pub const MAX_PUBLIC_MESSAGE: usize = 200;
+
+/// Finds the message a checker wants to show to the team, which is the last line of its stderr
+/// starting with `PUBLIC:`. Messages are cut off after [`MAX_PUBLIC_MESSAGE`] characters.
+///
+/// # Examples
+///
+/// ```
+/// # use gameserver::service::public_message;
+/// let stderr = b"PUBLIC: registering\nconnection reset\nPUBLIC: login failed\n";
+/// assert_eq!(public_message(stderr), Some("login failed".to_owned()));
+/// assert_eq!(public_message(b"debug output\n"), None);
+/// ```
+pub fn public_message(stderr: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(stderr)
+        .lines()
+        .rev()
+        .find(|line| line.starts_with("PUBLIC:"))
+        .map(|line| {
+            line["PUBLIC:".len()..]
+                .trim()
+                .chars()
+                .take(MAX_PUBLIC_MESSAGE)
+                .collect::<String>()
+        })
+        .filter(|message| !message.is_empty())
+}
+
+/// Turns a checker's exit code into an error unless it reported `Ok`, returning its stdout and
+/// public message.
+fn check_status(output: Output) -> Result<(Vec<u8>, Option<String>), ServiceError> {
+    let message = public_message(&output.stderr);
+    match CheckerStatus::from_exit_status(output.status) {
+        CheckerStatus::Ok => Ok((output.stdout, message)),
+        status => Err(ServiceError::Verdict(status, message)),
+    }
 }
 
 macro_rules! optional_path {
@@ -70,6 +287,7 @@ macro_rules! optional_path {
 impl Service {
     pub fn load_from_dir(
         gs_config: &Config,
+        checkers: &Registry,
         name: impl AsRef<str>,
         path: impl AsRef<Path>,
     ) -> Result<Self, ServiceError> {
@@ -89,111 +307,396 @@ impl Service {
         let config: ServiceConfig =
             toml::from_slice(&contents).map_err(ServiceError::ParseConfig)?;
 
-        optional_path!(let get_flag_path = (&config.get_flag_path, path.join("get_flag")));
-        optional_path!(let set_flag_path = (&config.set_flag_path, path.join("set_flag")));
-        optional_path!(let check_up_path = (&config.check_up_path, path.join("check_up")));
+        let backend = match &config.checker {
+            Some(url) if url.starts_with("http://") || url.starts_with("https://") => {
+                Backend::Native(Arc::new(url.parse::<RemoteChecker>()?))
+            }
+            Some(checker) => Backend::Native(checkers.resolve(checker)?),
+            None if config.check.is_some() => {
+                if !config.noise.is_empty() {
+                    return Err(ServiceError::NoNoise);
+                }
+                let check = config.check.clone().unwrap();
+                Backend::Generic(GenericChecker::new(check)?)
+            }
+            None if config.command.is_some() => {
+                let command = config.command.clone().unwrap();
+                if command.is_empty() {
+                    return Err(ServiceError::EmptyCommand);
+                }
+                let has_flag = |arg: &String| arg.contains("{flag}") || arg.contains("{flag_id}");
+                if config.invocation == Invocation::Env && command.iter().any(has_flag) {
+                    return Err(ServiceError::FlagInCommand);
+                }
+                Backend::Command(command)
+            }
+            None => {
+                optional_path!(let get_flag_path = (&config.get_flag_path, path.join("get_flag")));
+                optional_path!(let set_flag_path = (&config.set_flag_path, path.join("set_flag")));
+                optional_path!(let check_up_path = (&config.check_up_path, path.join("check_up")));
+                let mut noise_paths = HashMap::new();
+                for &noise in &config.noise {
+                    let configured = match noise {
+                        Noise::PutNoise => &config.put_noise_path,
+                        Noise::GetNoise => &config.get_noise_path,
+                        Noise::Havoc => &config.havoc_path,
+                    };
+                    optional_path!(let noise_path = (configured, path.join(noise.as_str())));
+                    noise_paths.insert(noise, noise_path);
+                }
+                Backend::Executables {
+                    get_flag_path,
+                    set_flag_path,
+                    check_up_path,
+                    noise_paths,
+                }
+            }
+        };
 
+        let limiter = config.max_checkers.map(Limiter::new);
+        let limits = config.limits.or(gs_config.limits);
         let service = Service {
             name: name.to_owned(),
+            timeout: config.timeout.unwrap_or(gs_config.timeout),
+            delay: config.delay.unwrap_or(gs_config.delay),
             config,
-            timeout: gs_config.timeout,
+            log_max_bytes: gs_config.log_max_bytes,
+            secret_key: gs_config.secret_key.clone(),
+            limits,
             base_dir: path.to_path_buf(),
-            get_flag_path,
-            set_flag_path,
-            check_up_path,
+            limiter,
+            backend,
         };
         Ok(service)
     }
 
-    pub fn get_flag(
+    /// Whether flags are planted in and retrieved from this service.
+    pub fn has_flags(&self) -> bool {
+        !matches!(self.backend, Backend::Generic(_))
+    }
+
+    /// How long `action` may take against this service.
+    pub fn timeout(&self, action: Action) -> Duration {
+        let timeout = self.config.timeouts.get(action).unwrap_or(self.timeout);
+        Duration::from_secs(timeout as u64)
+    }
+
+    /// Describes a run of `action` against `team`'s instance of the service. `tick` is the tick
+    /// the flag belongs to, or the check number for check_ups.
+    pub fn context(
         &self,
-        target: Ipv4Addr,
-        flag_id: Option<String>,
+        action: Action,
+        team: &TeamConfig,
+        tick: i32,
         log_dir: impl AsRef<Path>,
-    ) -> impl Future<Item = String, Error = ServiceError> {
-        let executable = self.get_flag_path.to_owned();
-        let port = self.config.port;
-
-        let mut args = vec![target.to_string(), port.to_string()];
-        if let Some(flag_id) = flag_id {
-            args.push(flag_id);
+    ) -> CheckContext {
+        let (target, port) = team.address(&self.name, self.config.port);
+        CheckContext {
+            action,
+            service: self.name.clone(),
+            team_id: team.id,
+            tick,
+            addr: target.ip(),
+            target,
+            port,
+            timeout: self.timeout(action),
+            log_dir: log_dir.as_ref().to_path_buf(),
         }
+    }
 
-        let child = TimeoutCommand::new(
-            executable,
-            &self.base_dir,
-            log_dir.as_ref(),
-            args,
-            Duration::from_secs(self.timeout as u64),
-        )
-        .map_err(ServiceError::Spawn);
+    pub fn get_flag(
+        &self,
+        ctx: CheckContext,
+        flag_id: Option<String>,
+    ) -> impl Future<Item = (String, Option<String>), Error = ServiceError> {
+        match &self.backend {
+            Backend::Native(checker) => {
+                let checker = checker.clone();
+                return Either::A(resolved(ctx, move |ctx| checker.get_flag(ctx, flag_id)));
+            }
+            Backend::Generic(_) => return Either::A(Box::new(future::err(ServiceError::NoFlags))),
+            Backend::Executables { .. } | Backend::Command(_) => (),
+        }
 
-        future::result(child)
-            .and_then(|child| child.map_err(ServiceError::Subprocess))
-            .and_then(|output| {
+        let fut = self
+            .run_executable(ctx, None, flag_id)
+            .and_then(|(output, message)| {
                 String::from_utf8(output)
-                    .map(|output| output.trim().to_owned())
+                    .map(|output| (output.trim().to_owned(), message))
                     .map_err(ServiceError::DecodeOutput)
-            })
+            });
+        Either::B(fut)
     }
 
     pub fn check_up(
         &self,
-        target: Ipv4Addr,
-        log_dir: impl AsRef<Path>,
-    ) -> impl Future<Item = (), Error = ServiceError> {
-        let name = self.name.clone();
-        let executable = self.check_up_path.to_owned();
-        let port = self.config.port;
-
-        let args = vec![target.to_string(), port.to_string()];
-        let child = TimeoutCommand::new(
-            executable,
-            &self.base_dir,
-            log_dir.as_ref(),
-            args,
-            Duration::from_secs(self.timeout as u64),
-        )
-        .map_err(ServiceError::Spawn);
-
-        future::result(child)
-            .and_then(|child| child.map_err(ServiceError::Subprocess))
-            .map(|_| ())
+        ctx: CheckContext,
+    ) -> impl Future<Item = Option<String>, Error = ServiceError> {
+        match &self.backend {
+            Backend::Native(checker) => {
+                let checker = checker.clone();
+                return Either::A(resolved(ctx, move |ctx| checker.check_up(ctx)));
+            }
+            Backend::Generic(checker) => {
+                let checker = checker.clone();
+                return Either::A(resolved(ctx, move |ctx| checker.check_up(ctx)));
+            }
+            Backend::Executables { .. } | Backend::Command(_) => (),
+        }
+
+        let fut = self
+            .run_executable(ctx, None, None)
+            .map(|(_, message)| message);
+        Either::B(fut)
+    }
+
+    /// Runs the noise action in `ctx.action`, returning its public message.
+    pub fn noise(
+        &self,
+        ctx: CheckContext,
+    ) -> impl Future<Item = Option<String>, Error = ServiceError> {
+        match &self.backend {
+            Backend::Native(checker) => {
+                let checker = checker.clone();
+                return Either::A(resolved(ctx, move |ctx| checker.noise(ctx)));
+            }
+            Backend::Generic(_) => return Either::A(Box::new(future::err(ServiceError::NoNoise))),
+            Backend::Executables { .. } | Backend::Command(_) => (),
+        }
+
+        let fut = self
+            .run_executable(ctx, None, None)
+            .map(|(_, message)| message);
+        Either::B(fut)
     }
 
     pub fn set_flag(
         &self,
-        target: Ipv4Addr,
+        ctx: CheckContext,
         flag: impl AsRef<str>,
-        log_dir: impl AsRef<Path>,
-    ) -> impl Future<Item = Option<String>, Error = ServiceError> {
+    ) -> impl Future<Item = (Option<String>, Option<String>), Error = ServiceError> {
         let flag = flag.as_ref().to_owned();
-        let executable = self.set_flag_path.to_owned();
-        let port = self.config.port;
-
-        let args = vec![target.to_string(), port.to_string(), flag];
-        let child = TimeoutCommand::new(
-            executable.clone(),
-            &self.base_dir,
-            log_dir.as_ref(),
-            args.clone(),
-            Duration::from_secs(self.timeout as u64),
-        )
-        .map_err(ServiceError::Spawn);
-
-        future::result(child)
-            .and_then(|child| child.map_err(ServiceError::Subprocess))
-            .and_then(|output| {
+        match &self.backend {
+            Backend::Native(checker) => {
+                let checker = checker.clone();
+                return Either::A(resolved(ctx, move |ctx| checker.set_flag(ctx, flag)));
+            }
+            Backend::Generic(_) => return Either::A(Box::new(future::err(ServiceError::NoFlags))),
+            Backend::Executables { .. } | Backend::Command(_) => (),
+        }
+
+        let fut = self
+            .run_executable(ctx, Some(flag), None)
+            .and_then(|(output, message)| {
                 String::from_utf8(output)
                     .map(|output| {
                         let output = output.trim().to_owned();
                         if !output.is_empty() {
-                            Some(output)
+                            (Some(output), message)
                         } else {
-                            None
+                            (None, message)
                         }
                     })
                     .map_err(ServiceError::DecodeOutput)
+            });
+        Either::B(fut)
+    }
+
+    /// Builds the command line for a run of the service's executables or `command`.
+    fn command_line(
+        &self,
+        ctx: &CheckContext,
+        flag: &Option<String>,
+        flag_id: &Option<String>,
+    ) -> Command {
+        let (check_up_path, set_flag_path, get_flag_path, noise_paths) = match &self.backend {
+            Backend::Command(template) => {
+                let mut args = template.iter().filter_map(|arg| {
+                    expand_template(arg, ctx, flag.as_deref(), flag_id.as_deref())
+                });
+                let mut cmd = Command::new(args.next().unwrap_or_default());
+                cmd.args(args);
+                return cmd;
+            }
+            Backend::Executables {
+                check_up_path,
+                set_flag_path,
+                get_flag_path,
+                noise_paths,
+            } => (check_up_path, set_flag_path, get_flag_path, noise_paths),
+            Backend::Native(_) | Backend::Generic(_) => unreachable!("not run as executables"),
+        };
+        let executable = match ctx.action {
+            Action::CheckUp => check_up_path,
+            Action::SetFlag => set_flag_path,
+            Action::GetFlag => get_flag_path,
+            Action::Noise(noise) => noise_paths
+                .get(&noise)
+                .expect("only the service's own noise actions are run"),
+        };
+
+        let mut cmd = match &self.config.interpreter {
+            Some(interpreter) => {
+                let mut cmd = Command::new(interpreter);
+                cmd.arg(executable);
+                cmd
+            }
+            None => Command::new(executable),
+        };
+        cmd.arg(ctx.target.to_string()).arg(ctx.port.to_string());
+        if self.config.invocation == Invocation::Args {
+            cmd.args(flag).args(flag_id);
+        }
+        cmd
+    }
+
+    /// Runs one of the service's executables, or its `command`, returning its stdout and public
+    /// message. How the flag, flag id and the rest of `ctx` are passed to it depends on the
+    /// service's [`Invocation`].
+    fn run_executable(
+        &self,
+        ctx: CheckContext,
+        flag: Option<String>,
+        flag_id: Option<String>,
+    ) -> impl Future<Item = (Vec<u8>, Option<String>), Error = ServiceError> {
+        let mut cmd = self.command_line(&ctx, &flag, &flag_id);
+        cmd.current_dir(&self.base_dir);
+
+        let mut input = None;
+        if self.config.invocation == Invocation::Env {
+            // set_flag and get_flag for the same flag share a seed
+            let purpose = match ctx.action {
+                Action::SetFlag | Action::GetFlag => "flag",
+                action => action.name(),
+            };
+            let seed = generate_seed(
+                self.secret_key.as_bytes(),
+                purpose,
+                ctx.tick,
+                ctx.team_id,
+                &ctx.service,
+            );
+            cmd.env("SERVICE", &ctx.service)
+                .env("TEAM_ID", ctx.team_id.to_string())
+                .env("TICK", ctx.tick.to_string())
+                .env("TIMEOUT", ctx.timeout.as_secs().to_string())
+                .env("SEED", seed.to_string());
+            if let Some(flag_id) = flag_id {
+                cmd.env("FLAG_ID", flag_id);
+            }
+            input = flag.map(|flag| format!("{}\n", flag).into_bytes());
+        }
+
+        self.limits.apply(&mut cmd);
+        // the checker is given the host as it is, but shouldn't be started if it doesn't resolve
+        let log_max_bytes = self.log_max_bytes;
+        let started = Instant::now();
+        limit_time(Box::new(ctx.target.resolve()), ctx.timeout)
+            .and_then(move |_| {
+                let timeout = ctx.timeout.saturating_sub(started.elapsed());
+                TimeoutCommand::spawn(cmd, ctx.log_dir, timeout, log_max_bytes, input)
+                    .map_err(ServiceError::Spawn)
             })
+            .and_then(|child| child.map_err(ServiceError::Subprocess))
+            .and_then(check_status)
     }
 }
+
+/// Fills in the placeholders of one argument of a service's `command`: `{action}` (see
+/// [`Action::as_str`]), `{ip}`, `{port}`, `{service}`, `{team_id}`, `{tick}`, `{timeout}`, `{flag}`
+/// and `{flag_id}`. An argument that is just `{flag}` or `{flag_id}` is left out when there
+/// isn't one, so templates can list them for every action.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::PathBuf;
+/// # use std::time::Duration;
+/// # use gameserver::checker::{Action, CheckContext};
+/// # use gameserver::service::expand_template;
+/// let ctx = CheckContext {
+///     action: Action::SetFlag,
+///     service: "echo".to_owned(),
+///     team_id: 1,
+///     tick: 4,
+///     target: "team1.ctf".parse().unwrap(),
+///     addr: None,
+///     port: 9999,
+///     timeout: Duration::from_secs(5),
+///     log_dir: PathBuf::from("logs"),
+/// };
+/// let expand = |arg| expand_template(arg, &ctx, Some("FLG"), None);
+/// assert_eq!(expand("{action}").unwrap(), "put");
+/// assert_eq!(expand("--target={ip}:{port}").unwrap(), "--target=team1.ctf:9999");
+/// assert_eq!(expand("{flag}").unwrap(), "FLG");
+/// assert_eq!(expand("{flag_id}"), None);
+/// assert_eq!(expand("{unknown}").unwrap(), "{unknown}");
+/// ```
+pub fn expand_template(
+    arg: &str,
+    ctx: &CheckContext,
+    flag: Option<&str>,
+    flag_id: Option<&str>,
+) -> Option<String> {
+    if (arg == "{flag}" && flag.is_none()) || (arg == "{flag_id}" && flag_id.is_none()) {
+        return None;
+    }
+    // a single pass, so values like a team's flag id aren't expanded in turn
+    let mut expanded = String::new();
+    let mut rest = arg;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+        let value = match &rest[1..end] {
+            "action" => ctx.action.as_str().to_owned(),
+            "ip" => ctx.target.to_string(),
+            "port" => ctx.port.to_string(),
+            "service" => ctx.service.clone(),
+            "team_id" => ctx.team_id.to_string(),
+            "tick" => ctx.tick.to_string(),
+            "timeout" => ctx.timeout.as_secs().to_string(),
+            "flag" => flag.unwrap_or_default().to_owned(),
+            "flag_id" => flag_id.unwrap_or_default().to_owned(),
+            _ => rest[..=end].to_owned(),
+        };
+        expanded.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    Some(expanded)
+}
+
+/// Looks up `ctx.target` before handing the run to `run`, so a hostname that doesn't resolve
+/// fails the run with [`ServiceError::Resolve`]. The lookup counts toward the run's timeout.
+fn resolved<T, F>(ctx: CheckContext, run: F) -> CheckFuture<T>
+where
+    T: Send + 'static,
+    F: FnOnce(CheckContext) -> CheckFuture<T> + Send + Sync + 'static,
+{
+    let timeout = ctx.timeout;
+    let run = ctx.target.resolve().and_then(move |addr| {
+        run(CheckContext {
+            addr: Some(addr),
+            ..ctx
+        })
+    });
+    limit_time(Box::new(run), timeout)
+}
+
+/// Gives up on a native checker once its time is up. Executables are timed out by
+/// [`TimeoutCommand`] instead, so their logs still get written.
+fn limit_time<T: Send + 'static>(fut: CheckFuture<T>, timeout: Duration) -> CheckFuture<T> {
+    Box::new(Timeout::new(fut, timeout).map_err(|err| {
+        if err.is_elapsed() {
+            ServiceError::TimedOut
+        } else if err.is_timer() {
+            ServiceError::Timer(err.into_timer().unwrap())
+        } else {
+            err.into_inner().unwrap()
+        }
+    }))
+}
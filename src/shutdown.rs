@@ -0,0 +1,115 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures::future::Shared;
+use futures::sync::oneshot;
+use tokio::prelude::*;
+use tokio_signal::unix::{Signal, SIGINT, SIGTERM};
+
+/// Coordinates a graceful shutdown.
+///
+/// Once a shutdown has been requested, the tickers stop scheduling new rounds and everything
+/// waiting on [`Shutdown::wait`] is woken up. Forcing it wakes up everything waiting on
+/// [`Shutdown::wait_forced`], which doesn't wait for running checkers any more. Clones share the
+/// same state.
+///
+/// # Examples
+///
+/// ```
+/// # use futures::Future;
+/// # use gameserver::shutdown::Shutdown;
+/// let shutdown = Shutdown::new();
+/// assert!(!shutdown.is_requested());
+///
+/// shutdown.clone().request();
+/// assert!(shutdown.is_requested());
+/// shutdown.wait().wait().unwrap();
+///
+/// shutdown.force();
+/// shutdown.wait_forced().wait().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+    trigger: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    signal: Shared<oneshot::Receiver<()>>,
+    force_trigger: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    force_signal: Shared<oneshot::Receiver<()>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (trigger, signal) = oneshot::channel();
+        let (force_trigger, force_signal) = oneshot::channel();
+        Shutdown {
+            requested: Arc::new(AtomicBool::new(false)),
+            trigger: Arc::new(Mutex::new(Some(trigger))),
+            signal: signal.shared(),
+            force_trigger: Arc::new(Mutex::new(Some(force_trigger))),
+            force_signal: force_signal.shared(),
+        }
+    }
+
+    /// Requests a shutdown. Calling this more than once has no further effect.
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+        if let Some(trigger) = self.trigger.lock().unwrap().take() {
+            let _ = trigger.send(());
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Returns a future that resolves once a shutdown has been requested.
+    pub fn wait(&self) -> impl Future<Item = (), Error = ()> + Send {
+        self.signal.clone().map(|_| ()).map_err(|_| ())
+    }
+
+    /// Requests a shutdown that doesn't wait for running checkers.
+    pub fn force(&self) {
+        self.request();
+        if let Some(trigger) = self.force_trigger.lock().unwrap().take() {
+            let _ = trigger.send(());
+        }
+    }
+
+    /// Returns a future that resolves once the shutdown has been forced.
+    pub fn wait_forced(&self) -> impl Future<Item = (), Error = ()> + Send {
+        self.force_signal.clone().map(|_| ()).map_err(|_| ())
+    }
+}
+
+/// Requests a shutdown when the process receives SIGINT or SIGTERM, and forces it when a second
+/// one arrives while the checkers are still being waited for.
+///
+/// The returned future resolves after the second signal. If the signal handlers can't be
+/// installed, the error is logged and the future resolves right away without requesting
+/// anything.
+pub fn listen(shutdown: Shutdown) -> impl Future<Item = (), Error = ()> {
+    Signal::new(SIGINT)
+        .join(Signal::new(SIGTERM))
+        .and_then(|(sigint, sigterm)| {
+            sigint.select(sigterm).take(2).for_each(move |signal| {
+                if shutdown.is_requested() {
+                    warn!(
+                        "received signal {} again, killing the remaining checkers",
+                        signal
+                    );
+                    shutdown.force();
+                } else {
+                    info!("received signal {}, shutting down", signal);
+                    shutdown.request();
+                }
+                Ok(())
+            })
+        })
+        .map_err(|err| error!("couldn't listen for signals: {}", err))
+}
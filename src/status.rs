@@ -0,0 +1,122 @@
+use std::io::Write;
+use std::process::ExitStatus;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+
+/// The verdict of a single checker run.
+///
+/// Checkers report it through their exit code:
+///
+/// | exit code       | status    | meaning                                          |
+/// |-----------------|-----------|--------------------------------------------------|
+/// | 0 or 101        | `Ok`      | everything works                                 |
+/// | 102             | `Corrupt` | the service works, but the flag is missing/wrong |
+/// | 103             | `Mumble`  | the service responds, but not correctly          |
+/// | 104             | `Down`    | the service can't be reached                     |
+/// | 110             | `Error`   | the checker itself is broken                     |
+///
+/// Any other exit code counts as `Down`, so checkers that just exit with 1 on failure keep
+/// working. A checker that times out is `Down`. One that's killed by a signal, like when the
+/// service's responses push it over a resource limit, is `Mumble`. One that can't be started is
+/// `Error`.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, AsExpression, FromSqlRow,
+)]
+#[serde(rename_all = "lowercase")]
+#[sql_type = "Text"]
+pub enum CheckerStatus {
+    Ok,
+    Mumble,
+    Corrupt,
+    Down,
+    Error,
+}
+
+impl CheckerStatus {
+    /// Maps a checker's exit code to its verdict.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use gameserver::status::CheckerStatus;
+    /// assert_eq!(CheckerStatus::from_exit_code(Some(0)), CheckerStatus::Ok);
+    /// assert_eq!(CheckerStatus::from_exit_code(Some(102)), CheckerStatus::Corrupt);
+    /// assert_eq!(CheckerStatus::from_exit_code(Some(1)), CheckerStatus::Down);
+    /// assert_eq!(CheckerStatus::from_exit_code(Some(110)), CheckerStatus::Error);
+    /// assert_eq!(CheckerStatus::from_exit_code(None), CheckerStatus::Mumble);
+    /// ```
+    pub fn from_exit_code(code: Option<i32>) -> Self {
+        match code {
+            Some(0) | Some(101) => CheckerStatus::Ok,
+            Some(102) => CheckerStatus::Corrupt,
+            Some(103) => CheckerStatus::Mumble,
+            Some(104) => CheckerStatus::Down,
+            Some(110) => CheckerStatus::Error,
+            Some(_) => CheckerStatus::Down,
+            // killed by a signal, which the service can cause by making the checker go over its
+            // memory or CPU limit
+            None => CheckerStatus::Mumble,
+        }
+    }
+
+    pub fn from_exit_status(status: ExitStatus) -> Self {
+        CheckerStatus::from_exit_code(status.code())
+    }
+
+    /// Whether the team gets credit for this result.
+    pub fn counts_as_up(self) -> bool {
+        self == CheckerStatus::Ok
+    }
+
+    /// Whether this result is held against the team. Checker errors are our fault rather than
+    /// the team's, so they count neither way.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use gameserver::status::CheckerStatus;
+    /// assert!(CheckerStatus::Down.counts_against());
+    /// assert!(!CheckerStatus::Error.counts_against());
+    /// assert!(!CheckerStatus::Error.counts_as_up());
+    /// ```
+    pub fn counts_against(self) -> bool {
+        !matches!(self, CheckerStatus::Ok | CheckerStatus::Error)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CheckerStatus::Ok => "ok",
+            CheckerStatus::Mumble => "mumble",
+            CheckerStatus::Corrupt => "corrupt",
+            CheckerStatus::Down => "down",
+            CheckerStatus::Error => "error",
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for CheckerStatus {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for CheckerStatus {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"ok" => Ok(CheckerStatus::Ok),
+            b"mumble" => Ok(CheckerStatus::Mumble),
+            b"corrupt" => Ok(CheckerStatus::Corrupt),
+            b"down" => Ok(CheckerStatus::Down),
+            b"error" => Ok(CheckerStatus::Error),
+            status => Err(format!(
+                "unknown checker status {:?}",
+                String::from_utf8_lossy(status)
+            )
+            .into()),
+        }
+    }
+}
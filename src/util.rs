@@ -1,27 +1,35 @@
 use std::error::Error;
 use std::ffi::OsStr;
 use std::fmt::Debug;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, Write};
+use std::os::unix::process::CommandExt as _;
 use std::path::{Path, PathBuf};
-use std::process::{Command, ExitStatus, Stdio};
+use std::process::{Command, ExitStatus, Output, Stdio};
 use std::sync::Mutex;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use futures::prelude::*;
-use tokio::io::{read_to_end, ReadToEnd};
-use tokio_process::{Child, ChildStderr, ChildStdout, CommandExt};
+use tokio::io::{write_all, AsyncRead, WriteAll};
+use tokio_process::{Child, ChildStderr, ChildStdin, ChildStdout, CommandExt};
+use tokio_signal::unix::Signal;
 use tokio_timer::{sleep, Delay};
 
 /// Runs a command with a timeout.
 ///
+/// The command's output is returned whatever its exit status was; it's only an error if the
+/// command couldn't be run or didn't finish in time. The command runs in its own process group,
+/// and when it exits, times out or is dropped before it's done, the whole group is killed,
+/// including anything it started.
+///
 /// # Examples
 ///
 /// ```
 /// # use futures::Future;
 /// # use gameserver::util::{TimeoutCommand, TimeoutCommandError};
 /// # use std::time::Duration;
-/// let cmd = TimeoutCommand::new("sleep", &["60"], Duration::from_secs(1)).unwrap();
+/// let logd = std::env::temp_dir().join("timeout-command-sleep");
+/// let cmd = TimeoutCommand::new("sleep", ".", logd, &["60"], Duration::from_secs(1), None).unwrap();
 /// tokio::run(cmd.then(|r| match r {
 ///     Err(TimeoutCommandError::TimedOut) => Ok(()),
 ///     r => panic!("r = {:?}", r),
@@ -32,33 +40,86 @@ use tokio_timer::{sleep, Delay};
 /// # use futures::Future;
 /// # use gameserver::util::{TimeoutCommand, TimeoutCommandError};
 /// # use std::time::Duration;
-/// let cmd = TimeoutCommand::new("echo", &["foo", "bar"], Duration::from_secs(1)).unwrap();
+/// let logd = std::env::temp_dir().join("timeout-command-echo");
+/// let cmd = TimeoutCommand::new("echo", ".", logd, &["foo", "bar"], Duration::from_secs(1), None)
+///     .unwrap();
 /// tokio::run(cmd.then(|r| match r {
-///     Ok(bs) => {
-///         assert_eq!(bs, b"foo bar");
+///     Ok(output) => {
+///         assert!(output.status.success());
+///         assert_eq!(output.stdout, b"foo bar\n");
 ///         Ok(())
 ///     },
 ///     r => panic!("r = {:?}", r),
 /// }));
 /// ```
+///
+/// Timing out kills what the command started too, so the background job here never gets to
+/// leave its marker:
+///
+/// ```
+/// # use futures::Future;
+/// # use gameserver::util::{TimeoutCommand, TimeoutCommandError};
+/// # use std::time::Duration;
+/// let logd = std::env::temp_dir().join("timeout-command-group");
+/// let marker = std::env::temp_dir().join("timeout-command-group-marker");
+/// let _ = std::fs::remove_file(&marker);
+/// let script = format!("(sleep 2; touch {}) & sleep 60", marker.display());
+/// let cmd = TimeoutCommand::new("sh", ".", logd, &["-c", &script], Duration::from_secs(1), None)
+///     .unwrap();
+/// tokio::run(cmd.then(|r| match r {
+///     Err(TimeoutCommandError::TimedOut) => Ok(()),
+///     r => panic!("r = {:?}", r),
+/// }));
+/// std::thread::sleep(Duration::from_secs(3));
+/// assert!(!marker.exists());
+/// ```
+///
+/// A command that writes more than the output limit is killed:
+///
+/// ```
+/// # use futures::Future;
+/// # use gameserver::util::{TimeoutCommand, TimeoutCommandError};
+/// # use std::time::Duration;
+/// let logd = std::env::temp_dir().join("timeout-command-yes");
+/// let cmd = TimeoutCommand::new("yes", ".", logd, Vec::<&str>::new(), Duration::from_secs(10), Some(1024))
+///     .unwrap();
+/// tokio::run(cmd.then(|r| match r {
+///     Err(TimeoutCommandError::OutputLimit) => Ok(()),
+///     r => panic!("r = {:?}", r),
+/// }));
+/// ```
 pub struct TimeoutCommand {
     child: Mutex<Child>,
-    done: bool,
+    pid: u32,
+    finished: bool,
+    status: Option<ExitStatus>,
+    /// Wakes the task up when a child exits, since the command is only reaped once its group
+    /// has been killed.
+    sigchld: Mutex<Box<dyn Stream<Item = (), Error = io::Error> + Send>>,
+    /// Whether the command's output went over the limit and it was killed for it.
+    killed_for_output: bool,
     timer: Delay,
+    started: Instant,
+    command: String,
     logd: PathBuf,
-    read_stdout: bool,
-    read_stderr: bool,
-    stdout: ReadToEnd<ChildStdout>,
-    // stderr: ReadToEnd<ChildStderr>,
+    stdin: Option<WriteAll<ChildStdin, Vec<u8>>>,
+    stdout: Capture<ChildStdout>,
+    stderr: Capture<ChildStderr>,
 }
 
 impl TimeoutCommand {
+    /// Spawns `command` in `wd`, killing it if it hasn't finished after `timeout`.
+    ///
+    /// When it's done, its stdout, stderr, exit status, duration and arguments are written to
+    /// `stdout.log`, `stderr.log` and `run.log` in `logd`. If `output_limit` is set, only that
+    /// many bytes of each stream are kept, and the command is killed if it writes more.
     pub fn new<I, P1, P2, S1, S2>(
         command: S1,
         wd: P1,
         logd: P2,
         args: I,
         timeout: Duration,
+        output_limit: Option<usize>,
     ) -> io::Result<TimeoutCommand>
     where
         I: IntoIterator<Item = S2> + Debug,
@@ -68,113 +129,357 @@ impl TimeoutCommand {
         S2: AsRef<OsStr>,
     {
         let mut cmd = Command::new(command.as_ref());
-        cmd.args(args)
-            .current_dir(wd)
-            .stdin(Stdio::null())
-            .stderr(Stdio::inherit())
-            .stdout(Stdio::piped());
+        cmd.args(args).current_dir(wd);
+        TimeoutCommand::spawn(cmd, logd, timeout, output_limit, None)
+    }
+
+    /// Like [`TimeoutCommand::new`], but runs an already set up `cmd`, writing `input` to its
+    /// stdin if there is any.
+    pub fn spawn(
+        mut cmd: Command,
+        logd: impl AsRef<Path>,
+        timeout: Duration,
+        output_limit: Option<usize>,
+        input: Option<Vec<u8>>,
+    ) -> io::Result<TimeoutCommand> {
+        cmd.process_group(0);
+        cmd.stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped());
         debug!("{:?}", cmd);
         cmd.spawn_async().map(|mut child| {
+            let pid = child.id();
+            let stdin = child
+                .stdin()
+                .take()
+                .and_then(|stdin| input.map(|input| write_all(stdin, input)));
             let stdout = child
                 .stdout()
                 .take()
                 .expect("for some reason no stdout was present");
-            // let stderr = child
-            //     .stderr()
-            //     .take()
-            //     .expect("for some reason no stderr was present");
+            let stderr = child
+                .stderr()
+                .take()
+                .expect("for some reason no stderr was present");
             TimeoutCommand {
                 child: Mutex::new(child),
-                done: false,
-                read_stdout: false,
-                read_stderr: false,
-                logd: logd.as_ref().to_path_buf(),
+                pid,
+                finished: false,
+                status: None,
+                sigchld: Mutex::new(Box::new(
+                    Signal::new(libc::SIGCHLD).flatten_stream().map(|_| ()),
+                )),
+                killed_for_output: false,
                 timer: sleep(timeout),
-                stdout: read_to_end(stdout, Vec::new()),
-                // stderr: read_to_end(stderr, Vec::new()),
+                started: Instant::now(),
+                command: format!("{:?}", cmd),
+                logd: logd.as_ref().to_path_buf(),
+                stdin,
+                stdout: Capture::new(stdout, output_limit),
+                stderr: Capture::new(stderr, output_limit),
             }
         })
     }
+
+    /// Writes everything we know about this run into the log directory. Failing to write the
+    /// logs shouldn't fail the check, so errors are only logged.
+    fn write_logs(&self, result: &str) {
+        let cut_off = |truncated| if truncated { " (cut off)" } else { "" };
+        let result = if self.killed_for_output {
+            format!("{} (killed for writing more than the output limit)", result)
+        } else {
+            result.to_owned()
+        };
+        let run = format!(
+            "command: {}\nresult: {}\nduration: {:?}\nstdout: {} bytes{}\nstderr: {} bytes{}\n",
+            self.command,
+            result,
+            self.started.elapsed(),
+            self.stdout.buf.len(),
+            cut_off(self.stdout.truncated),
+            self.stderr.buf.len(),
+            cut_off(self.stderr.truncated),
+        );
+        let write = || -> io::Result<()> {
+            fs::create_dir_all(&self.logd)?;
+            File::create(self.logd.join("stdout.log"))?.write_all(&self.stdout.buf)?;
+            File::create(self.logd.join("stderr.log"))?.write_all(&self.stderr.buf)?;
+            File::create(self.logd.join("run.log"))?.write_all(run.as_bytes())
+        };
+        if let Err(err) = write() {
+            warn!("couldn't write logs to {}: {}", self.logd.display(), err);
+        }
+    }
+}
+
+impl TimeoutCommand {
+    /// Whether the command has exited, without reaping it.
+    fn exited(&self) -> io::Result<bool> {
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let options = libc::WEXITED | libc::WNOHANG | libc::WNOWAIT;
+        if unsafe { libc::waitid(libc::P_PID, self.pid, &mut info, options) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { info.si_pid() } != 0)
+    }
+
+    /// Kills the command and everything else in its process group. This must only be called
+    /// before the command is reaped: until then its pid can't be reused, so neither can the
+    /// group's.
+    fn kill_group(&self) -> io::Result<()> {
+        // the group is named after the command's pid, and outlives the command itself as long
+        // as anything it started is still around
+        if unsafe { libc::killpg(self.pid as libc::pid_t, libc::SIGKILL) } == 0 {
+            return Ok(());
+        }
+        match io::Error::last_os_error() {
+            // everything in it has already exited
+            ref err if err.raw_os_error() == Some(libc::ESRCH) => Ok(()),
+            err => Err(err),
+        }
+    }
+}
+
+impl Drop for TimeoutCommand {
+    fn drop(&mut self) {
+        if !self.finished && self.status.is_none() {
+            if let Err(err) = self.kill_group() {
+                warn!("couldn't kill process group {}: {}", self.pid, err);
+            }
+        }
+    }
 }
 
 impl Future for TimeoutCommand {
-    type Item = Vec<u8>;
+    type Item = Output;
     type Error = TimeoutCommandError;
 
-    fn poll(&mut self) -> Result<Async<Vec<u8>>, TimeoutCommandError> {
-        if self.done {
-            match self.stdout.poll() {
-                Ok(Async::Ready((_, buf))) => Ok(Async::Ready(buf)),
-                Ok(Async::NotReady) => Ok(Async::NotReady),
-                Err(err) => Err(TimeoutCommandError::Io(err)),
+    fn poll(&mut self) -> Result<Async<Output>, TimeoutCommandError> {
+        // feed the input, closing stdin once it's all written
+        let written = match &mut self.stdin {
+            Some(stdin) => match stdin.poll() {
+                Ok(Async::NotReady) => false,
+                Ok(Async::Ready(_)) => true,
+                Err(err) => {
+                    debug!("couldn't write to stdin: {}", err);
+                    true
+                }
+            },
+            None => false,
+        };
+        if written {
+            self.stdin = None;
+        }
+
+        // keep draining both pipes, so the command never blocks on a full one
+        let stdout_done = self
+            .stdout
+            .poll_read()
+            .map_err(TimeoutCommandError::Io)?
+            .is_ready();
+        let stderr_done = self
+            .stderr
+            .poll_read()
+            .map_err(TimeoutCommandError::Io)?
+            .is_ready();
+
+        // a command that writes more than it may keep is stopped, rather than read forever
+        if (self.stdout.truncated || self.stderr.truncated)
+            && !self.killed_for_output
+            && self.status.is_none()
+        {
+            self.killed_for_output = true;
+            self.kill_group().map_err(TimeoutCommandError::Io)?;
+        }
+
+        if self.status.is_none() {
+            let mut sigchld = self.sigchld.lock().unwrap();
+            while let Async::Ready(Some(())) = sigchld.poll().map_err(TimeoutCommandError::Io)? {}
+            drop(sigchld);
+            // the rest of the group goes along with the command, and has to be killed before
+            // the command is reaped
+            if self.exited().map_err(TimeoutCommandError::Io)? {
+                self.kill_group().map_err(TimeoutCommandError::Io)?;
+                let status = self.child.lock().unwrap().poll();
+                match status {
+                    Ok(Async::Ready(status)) => self.status = Some(status),
+                    Ok(Async::NotReady) => (),
+                    Err(err) => return Err(TimeoutCommandError::Io(err)),
+                }
             }
-        // TODO: not the most ideal but works
-        // if !self.read_stderr {
-        //     match self.stderr.poll() {
-        //         Ok(Async::Ready((_, buf))) => {
-        //             File::create(self.logd.join("stderr.log"))
-        //                 .and_then(|mut f| f.write_all(&buf));
-        //             self.read_stderr = true;
-        //             Ok(Async::NotReady)
-        //         }
-        //         Ok(Async::NotReady) => Ok(Async::NotReady),
-        //         Err(err) => Err(TimeoutCommandError::Io(err)),
-        //     }
-        // } else {
-        //     match self.stdout.poll() {
-        //         Ok(Async::Ready((_, buf))) => {
-        //             File::create(self.logd.join("stdout.log"))
-        //                 .and_then(|mut f| f.write_all(&buf));
-        //             Ok(Async::Ready(buf))
-        //         }
-        //         Ok(Async::NotReady) => Ok(Async::NotReady),
-        //         Err(err) => Err(TimeoutCommandError::Io(err)),
-        //     }
-        // }
-        } else {
-            match self.timer.poll() {
-                Ok(Async::Ready(())) => {
-                    // We've timed out.
-                    let mut child = self.child.lock().unwrap();
-                    child.kill().map_err(TimeoutCommandError::Io)?;
-                    Err(TimeoutCommandError::TimedOut)
+        }
+
+        match self.status {
+            // We're done.
+            Some(status) if stdout_done && stderr_done => {
+                self.finished = true;
+                self.write_logs(&status.to_string());
+                if self.killed_for_output {
+                    return Err(TimeoutCommandError::OutputLimit);
                 }
-                Ok(Async::NotReady) => {
-                    let status = self.child.lock().unwrap().poll();
-                    match status {
-                        Ok(Async::Ready(status)) => {
-                            // We're done.
-                            if status.success() {
-                                self.done = true;
-                                self.poll()
-                            } else {
-                                Err(TimeoutCommandError::Exit(status))
-                            }
-                        }
-                        Ok(Async::NotReady) => Ok(Async::NotReady),
-                        Err(err) => Err(TimeoutCommandError::Io(err)),
+                return Ok(Async::Ready(Output {
+                    status,
+                    stdout: self.stdout.buf.split_off(0),
+                    stderr: self.stderr.buf.split_off(0),
+                }));
+            }
+            _ => (),
+        }
+
+        // the timeout also covers reading the output, in case something else is holding the
+        // pipes open after the command exited
+        match self.timer.poll() {
+            Ok(Async::Ready(())) => {
+                // We've timed out.
+                self.finished = true;
+                self.write_logs("timed out");
+                if self.status.is_none() {
+                    self.kill_group().map_err(TimeoutCommandError::Io)?;
+                }
+                Err(TimeoutCommandError::TimedOut)
+            }
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(err) => Err(TimeoutCommandError::Timer(err)),
+        }
+    }
+}
+
+/// Reads a stream to the end, or until more than `limit` bytes have come in.
+struct Capture<R> {
+    reader: Option<R>,
+    buf: Vec<u8>,
+    limit: Option<usize>,
+    /// Whether reading stopped at the limit.
+    truncated: bool,
+}
+
+impl<R: AsyncRead> Capture<R> {
+    fn new(reader: R, limit: Option<usize>) -> Self {
+        Capture {
+            reader: Some(reader),
+            buf: Vec::new(),
+            limit,
+            truncated: false,
+        }
+    }
+
+    /// Reads whatever is available right now. Ready once the stream has been closed or has gone
+    /// over the limit.
+    fn poll_read(&mut self) -> Poll<(), io::Error> {
+        let mut chunk = [0; 4096];
+        while let Some(reader) = &mut self.reader {
+            match reader.poll_read(&mut chunk)? {
+                Async::Ready(0) => self.reader = None,
+                Async::Ready(n) => {
+                    let keep = match self.limit {
+                        Some(limit) => n.min(limit.saturating_sub(self.buf.len())),
+                        None => n,
+                    };
+                    self.buf.extend_from_slice(&chunk[..keep]);
+                    if keep < n {
+                        self.truncated = true;
+                        self.reader = None;
                     }
                 }
-                Err(err) => Err(TimeoutCommandError::Timer(err)),
+                Async::NotReady => return Ok(Async::NotReady),
             }
         }
+        Ok(Async::Ready(()))
+    }
+}
+
+/// Resource limits for a checker, applied with `setrlimit` before it starts. Unset limits are
+/// left alone.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// CPU time in seconds.
+    pub cpu_seconds: Option<u64>,
+    /// Address space in bytes.
+    pub memory: Option<u64>,
+    pub open_files: Option<u64>,
+    /// Largest file the checker may write, in bytes.
+    pub file_size: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Takes each limit from `self` if it's set there, and from `fallback` otherwise.
+    pub fn or(self, fallback: ResourceLimits) -> ResourceLimits {
+        ResourceLimits {
+            cpu_seconds: self.cpu_seconds.or(fallback.cpu_seconds),
+            memory: self.memory.or(fallback.memory),
+            open_files: self.open_files.or(fallback.open_files),
+            file_size: self.file_size.or(fallback.file_size),
+        }
+    }
+
+    /// Makes `cmd` apply these limits to the process it starts.
+    pub fn apply(self, cmd: &mut Command) {
+        if self == ResourceLimits::default() {
+            return;
+        }
+        let limits = [
+            (libc::RLIMIT_CPU, self.cpu_seconds),
+            (libc::RLIMIT_AS, self.memory),
+            (libc::RLIMIT_NOFILE, self.open_files),
+            (libc::RLIMIT_FSIZE, self.file_size),
+        ];
+        // only async-signal-safe calls are allowed between fork and exec, which setrlimit is
+        unsafe {
+            cmd.pre_exec(move || {
+                for &(resource, limit) in &limits {
+                    if let Some(limit) = limit {
+                        let limit = libc::rlimit {
+                            rlim_cur: limit as libc::rlim_t,
+                            rlim_max: limit as libc::rlim_t,
+                        };
+                        if libc::setrlimit(resource, &limit) != 0 {
+                            return Err(io::Error::last_os_error());
+                        }
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+/// Makes a future that's only `Send` usable where a `Sync` one is needed, the same way
+/// [`TimeoutCommand`] holds its child.
+pub struct Locked<F>(Mutex<F>);
+
+impl<F> Locked<F> {
+    pub fn new(fut: F) -> Self {
+        Locked(Mutex::new(fut))
+    }
+}
+
+impl<F: Future> Future for Locked<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<F::Item, F::Error> {
+        self.0.get_mut().unwrap().poll()
     }
 }
 
 #[derive(Debug, Display)]
 pub enum TimeoutCommandError {
-    Exit(ExitStatus),
     Io(std::io::Error),
     TimedOut,
+    /// The command wrote more than the output limit and was killed.
+    OutputLimit,
     Timer(tokio_timer::Error),
 }
 
 impl Error for TimeoutCommandError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            TimeoutCommandError::Exit(_) => None,
             TimeoutCommandError::Io(err) => Some(err),
-            TimeoutCommandError::TimedOut => None,
+            TimeoutCommandError::TimedOut | TimeoutCommandError::OutputLimit => None,
             TimeoutCommandError::Timer(err) => Some(err),
         }
     }
@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use std::env;
+use std::ffi::CString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use crate::checker::Registry;
+use crate::config::{Config, ConfigError};
+use crate::db::Db;
+use crate::game::{read_services, GameServerError};
+use crate::host::Host;
+use crate::service::{Backend, Service, ServiceError};
+
+/// Longest service name the `services` table can hold.
+pub const MAX_SERVICE_NAME: usize = 16;
+
+/// How bad a [`Finding`] is. Errors make `gameserver validate` fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// Something wrong with the config, a service or the db.
+#[derive(Clone, Debug)]
+pub struct Finding {
+    pub severity: Severity,
+    /// What the finding is about, like `config`, `db` or `service notes`.
+    pub subject: String,
+    pub message: String,
+}
+
+/// Everything `gameserver validate` found.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+    /// How many services loaded.
+    pub services: usize,
+}
+
+impl Report {
+    fn error(&mut self, subject: impl Into<String>, message: impl Into<String>) {
+        self.push(Severity::Error, subject, message);
+    }
+
+    fn warning(&mut self, subject: impl Into<String>, message: impl Into<String>) {
+        self.push(Severity::Warning, subject, message);
+    }
+
+    fn push(&mut self, severity: Severity, subject: impl Into<String>, message: impl Into<String>) {
+        self.findings.push(Finding {
+            severity,
+            subject: subject.into(),
+            message: message.into(),
+        });
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.findings
+            .iter()
+            .any(|finding| finding.severity == Severity::Error)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for finding in &self.findings {
+            let severity = match finding.severity {
+                Severity::Warning => "warning",
+                Severity::Error => "error",
+            };
+            writeln!(
+                f,
+                "{:<8} {}: {}",
+                severity, finding.subject, finding.message
+            )?;
+        }
+        let count = |severity| {
+            self.findings
+                .iter()
+                .filter(|finding| finding.severity == severity)
+                .count()
+        };
+        write!(
+            f,
+            "{} services loaded, {} errors, {} warnings",
+            self.services,
+            count(Severity::Error),
+            count(Severity::Warning)
+        )
+    }
+}
+
+/// Checks the config at `config_path`, every service it refers to and the db, without changing
+/// anything, so problems show up before the game starts rather than during it.
+pub fn validate(config_path: impl AsRef<Path>, checkers: &Registry) -> Report {
+    let mut report = Report::default();
+    let config = match Config::load(config_path) {
+        Ok(config) => config,
+        Err(err) => {
+            let message = match err {
+                ConfigError::Open(err) => format!("can't be opened: {}", err),
+                ConfigError::Read(err) => format!("can't be read: {}", err),
+                ConfigError::Parse(err) => format!("is invalid: {}", err),
+            };
+            report.error("config", message);
+            return report;
+        }
+    };
+
+    check_teams(&config, &mut report);
+    if let Some(spread) = config.spread {
+        if !(0.0..=1.0).contains(&spread) {
+            report.error(
+                "config",
+                format!("spread is {}, not between 0 and 1", spread),
+            );
+        }
+    }
+    if config.admin_key.as_ref() == Some(&config.secret_key) {
+        report.error(
+            "config",
+            "admin_key is the same as secret_key, which would let admins work out checker seeds",
+        );
+    }
+    check_log_directory(&config.log_directory, &mut report);
+    check_services(&config, checkers, &mut report);
+    check_db(&config, &mut report);
+    report
+}
+
+fn check_teams(config: &Config, report: &mut Report) {
+    if config.teams.is_empty() {
+        report.warning("config", "there are no teams");
+    }
+    let mut ids = HashMap::new();
+    let mut ips = HashMap::new();
+    for team in &config.teams {
+        if let Some(other) = ids.insert(team.id, team) {
+            report.error(
+                "config",
+                format!(
+                    "the teams at {} and {} have the same id {}",
+                    other.ip, team.ip, team.id
+                ),
+            );
+        }
+        if let Some(other) = ips.insert(&team.ip, team) {
+            report.error(
+                "config",
+                format!(
+                    "teams {} and {} have the same ip {}",
+                    other.id, team.id, team.ip
+                ),
+            );
+        }
+        let overrides = team
+            .services
+            .values()
+            .filter_map(|address| address.ip.as_ref());
+        for host in std::iter::once(&team.ip).chain(overrides) {
+            check_host(team.id, host, report);
+        }
+    }
+}
+
+/// Checks that a team's `host` can be used. A hostname that doesn't resolve is only a warning,
+/// since it's looked up again for every run and the team may not have set it up yet.
+fn check_host(team_id: i32, host: &Host, report: &mut Report) {
+    if !host.is_valid() {
+        let message = format!(
+            "team {}'s {:?} isn't an IP address or hostname",
+            team_id,
+            host.as_str()
+        );
+        report.error("config", message);
+    } else if let Err(err) = host.lookup() {
+        report.warning(
+            "config",
+            format!("team {}'s {} can't be resolved: {}", team_id, host, err),
+        );
+    }
+}
+
+/// Checks that checker logs can be written, which the gameserver otherwise panics over. Nothing
+/// is created: the directory, or the nearest parent that exists if it doesn't yet, has to be
+/// writable.
+fn check_log_directory(log_directory: &Path, report: &mut Report) {
+    let subject = format!("log_directory {}", log_directory.display());
+    // a relative path's last ancestor is the empty path, which is the working directory
+    let existing = log_directory
+        .ancestors()
+        .map(|dir| {
+            if dir == Path::new("") {
+                Path::new(".")
+            } else {
+                dir
+            }
+        })
+        .find(|dir| dir.exists());
+    let existing = match existing {
+        Some(existing) => existing,
+        None => return report.error(subject, "can't be created: none of its parents exist"),
+    };
+    let message = if !existing.is_dir() {
+        format!("can't be created: {} isn't a directory", existing.display())
+    } else if let Err(err) = writable(existing) {
+        format!("isn't writable: {}: {}", existing.display(), err)
+    } else {
+        return;
+    };
+    report.error(subject, message);
+}
+
+/// Whether this process may create files in `dir`.
+fn writable(dir: &Path) -> io::Result<()> {
+    let path = CString::new(dir.as_os_str().as_bytes())?;
+    // access doesn't touch the directory, unlike writing a file to find out
+    if unsafe { libc::access(path.as_ptr(), libc::W_OK | libc::X_OK) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+fn check_services(config: &Config, checkers: &Registry, report: &mut Report) {
+    let services = match read_services(config, checkers) {
+        Ok(services) => services,
+        Err(err) => {
+            let subject = format!("services_dir {}", config.services_dir.display());
+            report.error(subject, format!("can't be read: {:?}", err));
+            return;
+        }
+    };
+    if services.is_empty() {
+        report.warning("config", "there are no services");
+    }
+    for team in &config.teams {
+        for name in team.services.keys() {
+            if !services.iter().any(|(service, _)| service == name) {
+                let message = format!(
+                    "team {} has an address for unknown service {}",
+                    team.id, name
+                );
+                report.warning("config", message);
+            }
+        }
+    }
+
+    for (name, service) in services {
+        let subject = format!("service {}", name);
+        if name.len() > MAX_SERVICE_NAME {
+            let message = format!(
+                "the name is longer than the {} characters the db can hold",
+                MAX_SERVICE_NAME
+            );
+            report.error(subject.clone(), message);
+        }
+        match service {
+            Ok(service) => {
+                report.services += 1;
+                check_executables(&service, &subject, report);
+            }
+            Err(GameServerError::Service(err)) => report.error(subject, describe(&err)),
+            Err(err) => report.error(subject, format!("{:?}", err)),
+        }
+    }
+
+    for name in &config.ignores {
+        if !config.services_dir.join(name).is_dir() {
+            report.warning("config", format!("ignored service {} doesn't exist", name));
+        }
+    }
+}
+
+/// Checks that whatever runs a service's checks can be run.
+fn check_executables(service: &Service, subject: &str, report: &mut Report) {
+    match &service.backend {
+        Backend::Executables {
+            get_flag_path,
+            set_flag_path,
+            check_up_path,
+            noise_paths,
+        } => {
+            let interpreter = service.config.interpreter.as_ref();
+            if let Some(interpreter) = interpreter {
+                check_program(interpreter, &service.base_dir, subject, report);
+            }
+            let paths = [check_up_path, set_flag_path, get_flag_path];
+            for path in paths.iter().copied().chain(noise_paths.values()) {
+                let path = service.base_dir.join(path);
+                // with an interpreter, the checkers are only read
+                if interpreter.is_none() && !is_executable(&path) {
+                    report.error(subject, format!("{} isn't executable", path.display()));
+                }
+            }
+        }
+        Backend::Command(command) => {
+            check_program(&command[0], &service.base_dir, subject, report);
+        }
+        Backend::Native(_) | Backend::Generic(_) => (),
+    }
+}
+
+/// Checks that `program` can be found the way [`std::process::Command`] would find it when run
+/// in `dir`.
+fn check_program(program: &str, dir: &Path, subject: &str, report: &mut Report) {
+    let found = if program.contains('/') {
+        is_executable(&dir.join(program))
+    } else {
+        let path = env::var_os("PATH").unwrap_or_default();
+        env::split_paths(&path).any(|dir| is_executable(&dir.join(program)))
+    };
+    if !found {
+        report.error(
+            subject,
+            format!("{} can't be found or isn't executable", program),
+        );
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path)
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+fn check_db(config: &Config, report: &mut Report) {
+    let pending = Db::connect(&config.db).and_then(|db| db.pending_migrations());
+    match pending {
+        Ok(pending) if pending.is_empty() => (),
+        Ok(pending) => report.error(
+            "db",
+            format!(
+                "the schema is out of date, run `gameserver migrate` to apply {}",
+                pending.join(", ")
+            ),
+        ),
+        Err(err) => report.error("db", format!("can't be checked: {}", err)),
+    }
+}
+
+/// A readable version of the common ways a service fails to load.
+fn describe(err: &ServiceError) -> String {
+    match err {
+        ServiceError::ConfigFileMissing => "meta.toml is missing".to_owned(),
+        ServiceError::OpenConfigFile(err) | ServiceError::ReadConfigFile(err) => {
+            format!("meta.toml can't be read: {}", err)
+        }
+        ServiceError::ParseConfig(err) => format!("meta.toml is invalid: {}", err),
+        ServiceError::FileNotFound(path) => format!("{} doesn't exist", path.display()),
+        ServiceError::UnknownChecker(name) => format!("there's no checker called {}", name),
+        ServiceError::HttpsChecker(url) => {
+            format!(
+                "{} uses https, but remote checkers only speak plain http",
+                url
+            )
+        }
+        ServiceError::Regex(err) => format!("the check's regex is invalid: {}", err),
+        ServiceError::RelativePath(path) => {
+            format!("the check's path {:?} doesn't start with /", path)
+        }
+        ServiceError::EmptyCommand => "the command is empty".to_owned(),
+        ServiceError::FlagInCommand => {
+            "the command can't use {flag} or {flag_id} with invocation = \"env\"".to_owned()
+        }
+        ServiceError::NoNoise => "generic checks can't have noise actions".to_owned(),
+        err => format!("{:?}", err),
+    }
+}
@@ -0,0 +1,74 @@
+use std::error::Error as StdError;
+use std::net::SocketAddr;
+
+use warp::http::StatusCode;
+use warp::{reject::custom as reject, Filter, Rejection};
+
+use crate::config::Config;
+use crate::db::Db;
+use crate::lifecycle::{apply, LifecycleError, TeamChange};
+use crate::reload::Reload;
+
+#[derive(Debug, Display)]
+enum Error {
+    WrongKey,
+}
+
+impl StdError for Error {}
+
+/// Only lets through requests whose `X-Admin-Key` header matches the configured admin key. If
+/// there isn't one, nothing is let through.
+fn authorized() -> impl Clone + Filter<Extract = (), Error = Rejection> {
+    warp::ext::get::<Config>()
+        .and(warp::header::<String>("x-admin-key"))
+        .and_then(|config: Config, key: String| {
+            let admin_key = config.admin_key.as_ref();
+            if admin_key
+                .is_some_and(|admin_key| constant_time_eq(key.as_bytes(), admin_key.as_bytes()))
+            {
+                Ok(())
+            } else {
+                Err(reject(Error::WrongKey))
+            }
+        })
+        .untuple_one()
+}
+
+/// Compares all of both keys, so how long it takes doesn't tell how much of a guess was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+pub fn reload() -> Resp!() {
+    authorized()
+        .and(warp::ext::get::<Reload>())
+        .map(|reload: Reload| {
+            reload.request();
+            warp::reply::json(&"reload scheduled for the next tick")
+        })
+        .boxed()
+}
+
+/// Makes the [`TeamChange`] in the JSON body, answering with what was done or why it wasn't.
+pub fn teams() -> Resp!() {
+    authorized()
+        .and(warp::ext::get::<Db>())
+        .and(warp::addr::remote())
+        .and(warp::body::json())
+        .map(|db: Db, addr: Option<SocketAddr>, change: TeamChange| {
+            let actor = match addr {
+                Some(addr) => format!("web ({})", addr),
+                None => "web".to_owned(),
+            };
+            let (message, status) = match apply(&db, &change, &actor) {
+                Ok(message) => (message, StatusCode::OK),
+                Err(LifecycleError::Db(err)) => {
+                    error!("{} {} failed: {:?}", actor, change.as_str(), err);
+                    (format!("{:?}", err), StatusCode::INTERNAL_SERVER_ERROR)
+                }
+                Err(err) => (err.to_string(), StatusCode::BAD_REQUEST),
+            };
+            warp::reply::with_status(warp::reply::json(&message), status)
+        })
+        .boxed()
+}
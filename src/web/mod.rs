@@ -1,27 +1,46 @@
 #[macro_use]
 mod utils;
 
+mod admin;
 mod scoreboard;
 mod submit_flag;
+mod teams;
 
 use std::net::SocketAddr;
 
+use tokio::prelude::*;
 use warp::Filter;
 
-use crate::config::Config;
+use crate::config::SharedConfig;
 use crate::db::Db;
-
-use self::utils::set;
-
-pub fn run(config: Config, bind_addr: SocketAddr, db: Db) {
-    let ext = set(db).and(set(config));
+use crate::reload::Reload;
+
+use self::utils::{set, set_with};
+
+/// Binds the web server. It stops accepting connections once `shutdown` resolves and finishes
+/// when the outstanding requests have been answered.
+pub fn serve(
+    config: SharedConfig,
+    bind_addr: SocketAddr,
+    db: Db,
+    reload: Reload,
+    shutdown: impl Future<Item = ()> + Send + 'static,
+) -> impl Future<Item = (), Error = ()> {
+    // the config is looked up for every request, so reloads reach the web server too
+    let ext = set(db).and(set_with(move || config.get())).and(set(reload));
 
     let routes = route_any!(
         POST("submit") => submit_flag::submit_flag(),
+        POST("admin" / "reload") => admin::reload(),
+        POST("admin" / "teams") => admin::teams(),
         GET("breakdown") => scoreboard::breakdown_only(),
         GET("check_up") => scoreboard::check_up_only(),
+        GET("teams") => teams::teams(),
         GET() => scoreboard::scoreboard(),
     );
 
-    warp::serve(ext.and(routes)).run(bind_addr)
+    let (addr, server) =
+        warp::serve(ext.and(routes)).bind_with_graceful_shutdown(bind_addr, shutdown);
+    info!("listening on http://{}", addr);
+    server
 }
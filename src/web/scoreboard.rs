@@ -9,6 +9,7 @@ use warp::{http::Response, reject::custom as reject, Filter, Rejection};
 use crate::config::Config;
 use crate::db::{Db, DbError};
 use crate::flag_io::calculate_round_length;
+use crate::lifecycle::TeamState;
 use crate::models::{CheckUp, Flag};
 
 lazy_static! {
@@ -25,9 +26,19 @@ lazy_static! {
 #[derive(Default, Serialize)]
 struct SummaryEntry {
     pub id: i32,
+    pub name: String,
+    pub affiliation: Option<String>,
+    pub country: Option<String>,
+    pub logo: Option<String>,
+    pub nop: bool,
+    pub suspended: bool,
+    pub disqualified: bool,
     pub atk_score: u32,
     pub def_score: u32,
     pub up_score: u32,
+    /// Up points lost to flags that couldn't be planted.
+    #[serde(skip)]
+    pub missed_up_score: u32,
     pub total_score: u32,
 }
 
@@ -99,6 +110,13 @@ fn scoreboard_ctx() -> impl Filter<Extract = (Context,), Error = Rejection> {
                         team.id,
                         SummaryEntry {
                             id: team.id,
+                            name: team.display_name(),
+                            affiliation: team.affiliation,
+                            country: team.country,
+                            logo: team.logo,
+                            nop: team.nop,
+                            suspended: team.state == TeamState::Suspended,
+                            disqualified: team.state == TeamState::Disqualified,
                             ..Default::default()
                         },
                     )
@@ -137,13 +155,19 @@ fn scoreboard_ctx() -> impl Filter<Extract = (Context,), Error = Rejection> {
                     .get(&flag.service_name)
                     .ok_or_else(|| Error::MissingService(flag.service_name.clone()))
                     .map_err(reject)?;
-                if let Some(team_id) = flag.claimed_by {
+                if flag.missed() {
+                    let team = teams
+                        .get_mut(&flag.team_id)
+                        .ok_or_else(|| Error::MissingTeam(flag.team_id))
+                        .map_err(reject)?;
+                    team.missed_up_score += service.up_score as u32;
+                } else if let Some(team_id) = flag.claimed_by {
                     let mut team = teams
                         .get_mut(&team_id)
                         .ok_or_else(|| Error::MissingTeam(team_id))
                         .map_err(reject)?;
                     team.atk_score += service.atk_score as u32;
-                } else if flag.defended {
+                } else if flag.defended() {
                     let mut team = teams
                         .get_mut(&flag.team_id)
                         .ok_or_else(|| Error::MissingTeam(flag.team_id))
@@ -179,7 +203,7 @@ fn scoreboard_ctx() -> impl Filter<Extract = (Context,), Error = Rejection> {
                     .ok_or_else(|| Error::MissingService(check_up.service_name.clone()))
                     .map_err(reject)?;
 
-                if check_up.up {
+                if check_up.status.counts_as_up() {
                     let mut team = teams
                         .get_mut(&check_up.team_id)
                         .ok_or_else(|| Error::MissingTeam(check_up.team_id))
@@ -189,15 +213,30 @@ fn scoreboard_ctx() -> impl Filter<Extract = (Context,), Error = Rejection> {
             }
 
             ctx.insert(
-                "teams",
+                "team_names",
                 &teams
-                    .values_mut()
-                    .map(|team| {
-                        team.total_score = team.atk_score + team.def_score + team.up_score;
-                        team
-                    })
-                    .collect::<Vec<_>>(),
+                    .values()
+                    .map(|team| (team.id, team.name.clone()))
+                    .collect::<HashMap<_, _>>(),
             );
+            let mut teams = teams
+                .into_values()
+                .map(|mut team| {
+                    // a flag that couldn't be planted costs as much as a failed check_up
+                    team.up_score = team.up_score.saturating_sub(team.missed_up_score);
+                    team.total_score = team.atk_score + team.def_score + team.up_score;
+                    team
+                })
+                .collect::<Vec<_>>();
+            // NOP and disqualified teams go last in the breakdown, and aren't ranked
+            teams.sort_unstable_by_key(|team| (team.disqualified, team.nop, team.id));
+            ctx.insert("columns", &teams);
+            let (disqualified, teams): (Vec<_>, Vec<_>) =
+                teams.into_iter().partition(|team| team.disqualified);
+            let (nop_teams, teams): (Vec<_>, Vec<_>) = teams.into_iter().partition(|team| team.nop);
+            ctx.insert("teams", &teams);
+            ctx.insert("nop_teams", &nop_teams);
+            ctx.insert("disqualified", &disqualified);
             ctx.insert("ticks", &{
                 let mut v = ticks
                     .into_iter()
@@ -1,7 +1,25 @@
-use warp::Filter;
+use std::error::Error as StdError;
 
+use warp::{reject::custom as reject, Filter};
+
+use crate::db::DbError;
+use crate::lifecycle::TeamState;
 use crate::Db;
 
+#[derive(Debug, Display)]
+enum Error {
+    Db(DbError),
+    Disqualified,
+}
+
+impl StdError for Error {}
+
+impl From<DbError> for Error {
+    fn from(err: DbError) -> Self {
+        Error::Db(err)
+    }
+}
+
 #[derive(Deserialize)]
 struct Form {
     team_id: i32,
@@ -12,20 +30,27 @@ pub fn submit_flag() -> Resp!() {
     warp::ext::get::<Db>()
         .and(warp::body::form())
         .and_then(|db: Db, form: Form| {
-            db.transaction(|| {
+            db.transaction(|tx| {
+                // disqualified teams can't score any more. the team's row stays locked until the
+                // claim is made, so a disqualification can't slip in between
+                let team = tx.get_team(form.team_id).map_err(Error::Db)?;
+                if team.is_some_and(|team| team.state == TeamState::Disqualified) {
+                    return Err(Error::Disqualified);
+                }
+
                 // look for this flag
-                let flag = db.lookup_flag(&form.flag)?;
+                let flag = tx.lookup_flag(&form.flag).map_err(Error::Db)?;
 
                 // check if this flag has already been claimed by another team
                 // also don't claim your own flag
                 if flag.claimed_by.is_some() || flag.team_id == form.team_id {
                 } else {
-                    db.claim_flag(&flag, form.team_id)?;
+                    tx.claim_flag(&flag, form.team_id).map_err(Error::Db)?;
                 }
 
                 Ok(())
             })
-            .map_err(warp::reject::custom)
+            .map_err(reject)
         })
         .map(|_| {
             let thanks = "thanks".to_owned();
@@ -0,0 +1,40 @@
+use warp::Filter;
+
+use crate::lifecycle::TeamState;
+use crate::models::Team;
+use crate::Db;
+
+/// What the scoreboard shows about a team. Addresses are left out.
+#[derive(Serialize)]
+struct TeamInfo {
+    id: i32,
+    name: String,
+    affiliation: Option<String>,
+    country: Option<String>,
+    logo: Option<String>,
+    /// Reference teams are checked and attacked, but not ranked.
+    nop: bool,
+    state: TeamState,
+}
+
+pub fn teams() -> Resp!() {
+    warp::ext::get::<Db>()
+        .and_then(|db: Db| db.get_all_teams().map_err(warp::reject::custom))
+        .map(|teams: Vec<Team>| {
+            let mut teams = teams
+                .into_iter()
+                .map(|team| TeamInfo {
+                    id: team.id,
+                    name: team.display_name(),
+                    affiliation: team.affiliation,
+                    country: team.country,
+                    logo: team.logo,
+                    nop: team.nop,
+                    state: team.state,
+                })
+                .collect::<Vec<_>>();
+            teams.sort_unstable_by_key(|team| team.id);
+            warp::reply::json(&teams)
+        })
+        .boxed()
+}
@@ -21,9 +21,16 @@ macro_rules! route_any {
 
 pub fn set<T: 'static + Clone + Send + Sync>(
     t: T,
+) -> impl Clone + Filter<Extract = (), Error = Rejection> {
+    set_with(move || t.clone())
+}
+
+/// Like [`set`], but calls `f` for every request.
+pub fn set_with<T: 'static + Send + Sync>(
+    f: impl 'static + Clone + Fn() -> T + Send + Sync,
 ) -> impl Clone + Filter<Extract = (), Error = Rejection> {
     warp::any()
-        .map(move || warp::ext::set(t.clone()))
+        .map(move || warp::ext::set(f()))
         .and_then(|()| -> Result<(), Rejection> { Ok(()) })
         .untuple_one()
 }